@@ -0,0 +1,226 @@
+use rchain_v1::rchain::{Block, ChainError, Hash, Keypair, Node, RpcServer, Transaction, TransactionData, WorldState};
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+const USAGE: &str = "\
+Usage: rchain-node [--data-dir <dir>] <command> [args]
+
+Commands:
+  init <spec file>                 Create a new chain from a chain spec
+  keygen                           Create a new key pair (the public key is the account id)
+                                   and print its secret key (store it in a key file)
+  transfer <from> <to> <amount>    Submit a token transfer
+      [--fee <fee>]                Fee offered to the producer of the block (default 0)
+      [--gas-limit <gas>]          Most gas the transaction may use (default 100000)
+      [--gas-price <price>]        Tokens paid for every unit of gas used (default 0)
+      [--nonce <nonce>]            Nonce of the transaction (default: the next free one)
+      [--key-file <path>]          File with the hex encoded secret key to sign the transaction with
+  pending                          List the transactions waiting for a block
+  mine <producer>                  Produce a block with the pending transactions
+      [--key-file <path>]          Seal the block as validator (Proof of Authority chains)
+  block <height | hash>            Print a block (heights start at 0)
+  account <id>                     Print the balance and the store of an account
+  validate [--full]                Check the chain (--full also replays every transaction)
+  serve [--port <port>]            Serve the chain via JSON-RPC on localhost (default port 8645)
+
+The data directory defaults to `./rchain-data`";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(args) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn run(mut args: Vec<String>) -> Result<(), String> {
+    let dir = take_option(&mut args, "--data-dir")?.unwrap_or_else(|| "rchain-data".into());
+    if args.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let command = args.remove(0);
+    match command.as_str() {
+        "init" => {
+            let [spec_path] = positional::<1>(args)?;
+            let node = Node::init(&dir, &spec_path).map_err(|err| err.to_string())?;
+            let chain = node.get_chain();
+            println!("Initialized chain {:?} in `{}`", chain.get_chain_id(), dir);
+            println!("Genesis block: {}", chain.get_last_block_hash().unwrap());
+        }
+
+        "keygen" => {
+            positional::<0>(args)?;
+            let keypair = Keypair::generate();
+            println!("Account id: {}", keypair.account_id());
+            println!("Secret key: {}", hex::encode(keypair.secret_bytes()));
+        }
+
+        "transfer" => {
+            let fee = parse_number(take_option(&mut args, "--fee")?.as_deref().unwrap_or("0"))?;
+            let gas_limit = take_option(&mut args, "--gas-limit")?;
+            let gas_price = parse_number(take_option(&mut args, "--gas-price")?.as_deref().unwrap_or("0"))?;
+            let nonce = take_option(&mut args, "--nonce")?;
+            let key = take_option(&mut args, "--key-file")?;
+            let [from, to, amount] = positional::<3>(args)?;
+
+            let mut node = open(&dir)?;
+            let nonce = match nonce {
+                Some(nonce) => parse_number(&nonce)?,
+                None => node.get_chain().next_nonce(&from)
+                    .ok_or_else(|| format!("Account `{}` does not exist", from))?,
+            };
+
+            let mut transaction = Transaction::new(from, TransactionData::TransferTokens {
+                to,
+                amount: parse_number(&amount)?,
+            }, nonce);
+            transaction.set_fee(fee);
+            transaction.set_gas_price(gas_price);
+            if let Some(gas_limit) = gas_limit {
+                transaction.set_gas_limit(gas_limit.parse()
+                    .map_err(|_| format!("`{}` is not a valid gas limit", gas_limit))?);
+            }
+
+            if let Some(key) = key {
+                transaction.sign(&read_key(&key)?).map_err(|err| err.to_string())?;
+            }
+
+            let hash = transaction.calculate_hash();
+            node.submit_transaction(transaction).map_err(|err| err.to_string())?;
+            println!("Submitted transaction {} (nonce {})", hash, nonce);
+        }
+
+        "pending" => {
+            positional::<0>(args)?;
+            let node = open(&dir)?;
+            for transaction in node.get_chain().get_pending_transactions() {
+                println!("{} {:#?}", transaction.calculate_hash(), transaction);
+            }
+        }
+
+        "mine" => {
+            let key = take_option(&mut args, "--key-file")?;
+            let [producer] = positional::<1>(args)?;
+            let mut node = open(&dir)?;
+            let block = match key {
+                Some(key) => node.produce_sealed_block(producer, &read_key(&key)?),
+                None => node.produce_block(producer),
+            }.map_err(|err| err.to_string())?;
+            println!("Block #{} added: {} ({} transactions)", node.get_chain().len() - 1,
+                     block.calculate_hash(), block.get_transaction_count());
+        }
+
+        "block" => {
+            let [id] = positional::<1>(args)?;
+            let node = open(&dir)?;
+            let chain = node.get_chain();
+
+            let height = match id.parse::<usize>() {
+                Ok(height) => height,
+                Err(_) => {
+                    let hash: Hash = id.parse().map_err(|err: ChainError| err.to_string())?;
+                    chain.get_height(&hash).ok_or_else(|| format!("No block with hash {}", hash))?
+                }
+            };
+
+            let block: &Block = chain.blocks.get(height)
+                .ok_or_else(|| format!("No block at height {} (the chain has {} blocks)", height, chain.len()))?;
+            println!("Block #{} {}", height, block.calculate_hash());
+            println!("{:#?}", block);
+        }
+
+        "account" => {
+            let [id] = positional::<1>(args)?;
+            let node = open(&dir)?;
+            let account = node.get_chain().get_account_by_id(&id)
+                .ok_or_else(|| format!("Account `{}` does not exist", id))?;
+
+            println!("Account: {}", id);
+            println!("Type: {:?}", account.get_account_type());
+            println!("Balance: {}", account.get_tokens());
+            println!("Nonce: {}", account.get_nonce());
+
+            let mut store: Vec<_> = account.get_store().iter().collect();
+            store.sort();
+            for (key, value) in store {
+                println!("  {} = {}", key, value);
+            }
+        }
+
+        "validate" => {
+            let full = take_flag(&mut args, "--full");
+            positional::<0>(args)?;
+            let node = open(&dir)?;
+            let chain = node.get_chain();
+
+            let result = if full { chain.check_full_validity() } else { chain.check_validity() };
+            match result {
+                Ok(()) => println!("Chain is valid ({} blocks)", chain.len()),
+                Err(err) => return Err(format!("Chain is invalid: {}", err)),
+            }
+        }
+
+        "serve" => {
+            let port = take_option(&mut args, "--port")?.unwrap_or_else(|| "8645".into());
+            let port = port.parse().map_err(|_| format!("`{}` is not a valid port", port))?;
+            positional::<0>(args)?;
+
+            let node = Arc::new(Mutex::new(open(&dir)?));
+            let server = RpcServer::start(port, node).map_err(|err| err.to_string())?;
+            println!("Serving JSON-RPC on http://{}", server.get_address());
+            server.wait();
+        }
+
+        _ => return Err(format!("Unknown command `{}`\n\n{}", command, USAGE)),
+    }
+
+    Ok(())
+}
+
+fn open(dir: &str) -> Result<Node, String> {
+    Node::open(dir).map_err(|err| format!("Could not open the chain in `{}`: {}", dir, err))
+}
+
+/// Will remove `--name <value>` from the arguments and return the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let position = match args.iter().position(|arg| arg == name) {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+
+    if position + 1 >= args.len() {
+        return Err(format!("`{}` needs a value", name));
+    }
+
+    args.remove(position);
+    Ok(Some(args.remove(position)))
+}
+
+/// Will remove `--name` from the arguments and tell if it was there
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let found = args.iter().any(|arg| arg == name);
+    args.retain(|arg| arg != name);
+    found
+}
+
+/// Will check that exactly N arguments are left
+fn positional<const N: usize>(args: Vec<String>) -> Result<[String; N], String> {
+    let count = args.len();
+    args.try_into().map_err(|_| format!("Expected {} arguments, but got {}\n\n{}", N, count, USAGE))
+}
+
+fn parse_number(value: &str) -> Result<u128, String> {
+    value.parse().map_err(|_| format!("`{}` is not a valid number", value))
+}
+
+/// Will read the key pair from a file containing its hex encoded secret key
+/// (a key given on the command line would show up in the process list and shell history)
+fn read_key(path: &str) -> Result<Keypair, String> {
+    let key = fs::read_to_string(path).map_err(|err| format!("Could not read `{}`: {}", path, err))?;
+    let secret = hex::decode(key.trim()).map_err(|_| format!("The secret key in `{}` is not hex encoded", path))?;
+    Keypair::from_secret_bytes(&secret).map_err(|err| err.to_string())
+}
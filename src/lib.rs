@@ -0,0 +1,6 @@
+//! RChain - a very basic blockchain implementation for educational purposes
+//!
+//! The actual chain logic lives within the `rchain` module. The demo binary (`main.rs`)
+//! and any other consumer just link against this library.
+
+pub mod rchain;
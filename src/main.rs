@@ -1,48 +1,69 @@
-mod rchain;
-use rchain::{Blockchain, Block, Transaction, TransactionData};
+use rchain_v1::rchain::{Blockchain, Block, ChainSpec, Transaction, TransactionData, Keypair, LightClient, WorldState, ChainError, Severity};
 use std::borrow::BorrowMut;
+use std::time::Instant;
 
 fn main() {
     println!("Demo RChain Version 1\n---------");
 
-    // Create a new Blockchain
-    let mut bc = Blockchain::new();
+    // Create a new Blockchain from the chain spec (every block has to be mined)
+    // The genesis block is built from the spec, so every node gets the same one
+    let spec = ChainSpec::parse(include_str!("../genesis.toml")).unwrap();
+    let mut bc = Blockchain::with_spec(&spec);
 
-    // Create an empty block (first block has no prev_block)
-    let mut genesis = Block::new(None);
-
-    let initial_users = vec!("alice", "bob");
-
-    for user in initial_users {
-        let create_transaction = Transaction::new(user.into(),
-                                                 TransactionData::CreateUserAccount(user.into()),
-                                                 0);
-
-        let token_action = Transaction::new(user.into(),
-                                            TransactionData::CreateTokens {receiver: user.into(), amount: 100_000_000},
-                                            0);
-
-        genesis.add_transaction(create_transaction);
-
-        genesis.add_transaction(token_action);
-    }
-
-    let mut res = bc.append_block(genesis);
-    println!("Genesis block successfully added: {:?}", res);
+    let mut res = bc.append_block(spec.genesis_block());
+    println!("Genesis block successfully added: {}", describe(res));
     println!("Full blockchain printout");
     println!("{:#?}", bc);
 
-    // Transfer 1 token from alice to bob
-    let mut block2 = Block::new(bc.get_last_block_hash());
-    block2.add_transaction(Transaction::new(
+    // Transfer 1 token from alice to bob (her first transaction, so it uses nonce 0)
+    // The transaction waits within the pending transactions until somebody creates a block
+    res = bc.submit_transaction(Transaction::new(
         "alice".into(),
         TransactionData::TransferTokens {to: "bob".into(), amount: 1}, 0));
+    println!("Transaction submitted: {}", describe(res));
+
+    // Alice can't spend more than she has, so that one is rejected right away
+    res = bc.submit_transaction(Transaction::new(
+        "alice".into(),
+        TransactionData::TransferTokens {to: "bob".into(), amount: 1_000_000_000}, 1));
+    println!("Transaction submitted: {}", describe(res));
+
+    let mut block2 = bc.build_block_template();
+    block2.mine(bc.get_difficulty());
 
     res = bc.append_block(block2);
-    println!("Block added: {:?}", res);
+    println!("Block added: {}", describe(res));
+    println!("Pending transactions left: {}", bc.get_pending_transactions().len());
+
+    // bob wants everybody to know his favorite color (stored within his own account)
+    // He offers a fee of 5 tokens to whoever includes it
+    let mut color_transaction = Transaction::new(
+        "bob".into(),
+        TransactionData::ChangeStoreValue {key: "color".into(), value: "blue".into()}, 0);
+    color_transaction.set_fee(5);
+    res = bc.submit_transaction(color_transaction);
+    println!("Transaction submitted: {}", describe(res));
+
+    // alice produces the next block, so she gets the reward and the fee
+    let mut block3 = bc.build_block_template_for("alice".into());
+    block3.mine(bc.get_difficulty());
+    println!("Block added: {}", describe(bc.append_block(block3)));
+    println!("alice's balance: {:?}", bc.get_balance("alice"));
+    println!("bob's favorite color: {:?}", bc.get_store_value("bob", "color"));
     println!("Full blockchain printout");
     println!("{:#?}", bc);
-    println!("Blockchain valid: {:?}", bc.check_validity());
+    println!("Blockchain valid: {}", describe(bc.check_validity()));
+
+    // A light client only keeps the headers, but still checks the work of every block
+    // (and that the chain starts with the genesis block of the spec)
+    let mut light_client = LightClient::new(spec.params.clone(), spec.genesis_hash());
+    println!("Light client synced: {}", describe(light_client.sync_from(&bc)));
+
+    // bob can prove to the light client that alice's transfer is part of the chain
+    let transfer_hash = bc.blocks[1].transactions[0].calculate_hash();
+    let proof = bc.prove_transaction(&transfer_hash).unwrap();
+    println!("Transfer {} is in block {}: {}", proof.transaction_hash, proof.block_hash,
+             light_client.verify_inclusion(&proof));
 
     // Everything is fine until here
 
@@ -56,45 +77,126 @@ fn main() {
     let transaction_data = bc_attack_1.blocks[1].transactions[0].borrow_mut();
 
     // change the amount value of the transaction INSIDE the chain
-    match transaction_data.record.borrow_mut() {
-        &mut TransactionData::TransferTokens {to:_, ref mut amount} => {
-            *amount = 100; // Actually change the value in place
-        },
-
-        _ => {} // We know that that recors is a TransferToken Action so we ignore the rest
+    // (We know that that record is a TransferToken Action so we ignore the rest)
+    if let &mut TransactionData::TransferTokens {to:_, ref mut amount} = transaction_data.record.borrow_mut() {
+        *amount = 100; // Actually change the value in place
     }
 
     println!("Changed transaction: {:?}", transaction_data.record);
 
     // Will print an error, since the blocks hash changes for the
-    println!("Is the Blockchain still valid? {:#?}", bc_attack_1.check_validity());
+    print_audit(&bc_attack_1);
 
     // Attack II: Changing transaction + updating the hash (increasing initial tokens in create
     // user action)
     let mut bc_attack_2 = bc.clone();
 
     // Alice tokens
-    let transaction_data= bc_attack_2.blocks[0].transactions[1].borrow_mut();
+    let transaction_data= bc_attack_2.blocks[0].transactions[2].borrow_mut();
 
     // change tokens
-    match transaction_data.record.borrow_mut() {
-        &mut TransactionData::CreateTokens {receiver: _, ref mut amount} => {
-            *amount = 100_000_000_000; // Let's dont be small on that
-        },
-        _ => {} // We know that that record is a Token Create Action so we ignore the rest
+    // (We know that that record is a Token Create Action so we ignore the rest)
+    if let &mut TransactionData::CreateTokens {receiver: _, ref mut amount} = transaction_data.record.borrow_mut() {
+        *amount = 100_000_000_000; // Let's dont be small on that
     }
 
     // If we execute now, we'll see the same error as above, hashes dont match (this time 1st block)
 
     // Will print an error, since the blocks hash changes for the
-    println!("Is the Blockchain still valid? {:#?}", bc_attack_2.check_validity());
+    print_audit(&bc_attack_2);
 
     // But alice was smart, she also updated the first blocks' hash
     bc_attack_2.blocks[0].update_hash();
 
+    // However, the new hash doesn't fulfill the Proof of Work anymore (most likely)
+    print_audit(&bc_attack_2);
+
+    // So she has to do the work for that block again
+    let started = Instant::now();
+    bc_attack_2.blocks[0].mine(spec.params.initial_difficulty);
+    println!("Re-mining the genesis block took {:?}", started.elapsed());
+
     // So the hash is correct now, however, block2 points now to sth which does not exists
     // Again, the blockchain is invalid but for a different reason
-    println!("Is the Blockchain still valid? {:#?}", bc_attack_2.check_validity());
+    // To fix that, alice would have to re-mine every single block after the genesis block
+    // (and be faster than everybody else who extends the real chain)
+    print_audit(&bc_attack_2);
+
+    // Attack III: replaying a transaction
+    // bob would love to receive alice's token again, so he just puts her transaction into a new block
+    let mut bc_attack_3 = bc.clone();
+    let mut block3 = Block::new(bc_attack_3.get_last_block_hash());
+    block3.add_transaction(bc_attack_3.blocks[1].transactions[0].clone());
+    block3.mine(bc_attack_3.get_difficulty());
+
+    // alice already used nonce 0 (her next one is 1), so the block is rejected
+    println!("Replayed transaction accepted? {}", describe(bc_attack_3.append_block(block3)));
+
+    // Attack IV: editing the world state directly
+    // The blocks are untouched, so only replaying all transactions reveals it
+    let mut bc_attack_4 = bc.clone();
+    // bob just copies alice's account (and with it her tokens) over his own
+    let alice_account = bc_attack_4.accounts["alice"].clone();
+    bc_attack_4.accounts.insert("bob".into(), alice_account);
+    println!("Blockchain valid: {}", describe(bc_attack_4.check_validity()));
+    println!("Blockchain fully valid: {}", describe(bc_attack_4.check_full_validity()));
+
+    // Attack V: spending tokens of someone else
+    // On a chain which requires signatures, accounts are identified by their public keys
+    // and only the owner of the private key is able to send transactions for them
+    let mut bc_signed = Blockchain::new();
+    bc_signed.set_require_signatures(true);
+
+    let carol = Keypair::generate();
+    let mallory = Keypair::generate();
+
+    let mut genesis = Block::new(None);
+    for key in [&carol, &mallory].iter() {
+        let mut create_transaction = Transaction::new(key.account_id(),
+                                                      TransactionData::CreateUserAccount(key.account_id()),
+                                                      0);
+        create_transaction.sign(key).unwrap();
+        genesis.add_transaction(create_transaction);
+    }
+
+    let mut token_action = Transaction::new(carol.account_id(),
+                                            TransactionData::CreateTokens {receiver: carol.account_id(), amount: 1_000},
+                                            0);
+    token_action.sign(&carol).unwrap();
+    genesis.add_transaction(token_action);
+    println!("Signed genesis block added: {}", describe(bc_signed.append_block(genesis)));
 
+    // Mallory claims to be carol, but she can't sign for her (unsigned transaction)
+    let mut block2 = Block::new(bc_signed.get_last_block_hash());
+    block2.add_transaction(Transaction::new(
+        carol.account_id(),
+        TransactionData::TransferTokens {to: mallory.account_id(), amount: 1_000}, 1));
+    println!("Unsigned theft accepted? {}", describe(bc_signed.append_block(block2)));
+
+    // ... and signing with her own key doesn't help either
+    let mut theft = Transaction::new(
+        carol.account_id(),
+        TransactionData::TransferTokens {to: mallory.account_id(), amount: 1_000}, 1);
+    println!("Sign with foreign key: {}", describe(theft.sign(&mallory)));
+}
+
+/// Will turn the result of a chain operation into a readable message
+fn describe(result: Result<(), ChainError>) -> String {
+    match result {
+        Ok(()) => "Ok".into(),
+        Err(err) => format!("Err: {}", err),
+    }
+}
+
+/// Will print every error of the chain (the demo chains aren't signed, so warnings are skipped)
+fn print_audit(bc: &Blockchain) {
+    let report = bc.audit();
+    match report.last_trusted_height {
+        Some(height) => println!("Audit: trusted up to Block #{}", height + 1),
+        None => println!("Audit: no block can be trusted"),
+    }
 
+    for finding in report.findings.iter().filter(|finding| finding.severity == Severity::Error) {
+        println!("  {}", finding.error);
+    }
 }
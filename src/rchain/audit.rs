@@ -0,0 +1,367 @@
+use super::{Account, Blockchain, ChainError, TransactionData, TransactionError};
+use super::authority::keep_rejected_blocks;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How bad a finding of an audit is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Allowed by the rules of the chain, but worth a look (e.g., unsigned transactions)
+    Warning,
+
+    /// Breaks the rules of the chain, the block can't be trusted
+    Error,
+}
+
+/// A single problem found by an audit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+
+    /// Height of the block the problem was found in (0 is the genesis block)
+    pub block: usize,
+
+    pub error: ChainError,
+}
+
+/// The result of checking the whole chain (see `Blockchain::audit`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditReport {
+    /// Every problem found, in chain order
+    pub findings: Vec<Finding>,
+
+    /// Height of the last block which (together with all blocks before it) has no errors
+    /// None if not even the genesis block can be trusted (or there are no blocks at all)
+    pub last_trusted_height: Option<usize>,
+}
+
+impl AuditReport {
+    /// Will tell if the chain has no errors (warnings are fine)
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Will return all findings with severity `Error`
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|finding| finding.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.last_trusted_height {
+            Some(height) => writeln!(f, "Trusted up to Block #{}", height + 1)?,
+            None => writeln!(f, "No block can be trusted")?,
+        }
+
+        for finding in self.findings.iter() {
+            writeln!(f, "[{:?}] {}", finding.severity, finding.error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Blockchain {
+    /// Checks the whole chain and reports every problem instead of stopping at the first one
+    /// (bad hashes, broken links, missing work, out of order nonces and bad signatures)
+    pub fn audit(&self) -> AuditReport {
+        let mut findings = Vec::new();
+        let mut last_trusted_height = None;
+
+        // Tracks the next nonce of every account created so far
+        let mut next_nonces: HashMap<String, u128> = HashMap::new();
+
+        for (block_num, block) in self.blocks.iter().enumerate() {
+            // Every problem of the block along with its severity
+            let mut problems = Vec::new();
+
+            // Check if block saved hash matches to calculated hash
+            if !block.verify_own_hash() {
+                problems.push((Severity::Error, ChainError::StoredHashMismatch { block: block_num }));
+            }
+
+            // Check if the genesis block is the one of the spec
+            if let (0, Some(expected)) = (block_num, self.genesis_hash) {
+                if block.calculate_hash() != expected {
+                    problems.push((Severity::Error, ChainError::GenesisMismatch {
+                        expected,
+                        actual: block.calculate_hash(),
+                    }));
+                }
+            }
+
+            // Check if the block is connected to the previous one and the work was done
+            problems.extend(self.params.header_errors(&self.blocks, block_num).into_iter()
+                .map(|error| (Severity::Error, error)));
+
+            // With Proof of Authority, every block but the genesis block is signed by its validator
+            // (whether it was the validators' turn depends on the state, see `verify_state`)
+            if self.params.proof_of_authority && block_num > 0 && !block.check_signature() {
+                problems.push((Severity::Error, ChainError::InvalidBlockSignature {
+                    block: block_num,
+                    validator: block.get_validator().cloned().unwrap_or_default(),
+                }));
+            }
+
+            for (transaction_num, transaction) in block.transactions.iter().enumerate() {
+
+                // Check if the sender uses its nonces in order (no replayed transactions)
+                // Accounts not created yet (initial transactions) can't be checked
+                // and the coinbase has no sender at all
+                if let Some(next_nonce) = next_nonces.get_mut(&transaction.from).filter(|_| !transaction.is_coinbase()) {
+                    if transaction.nonce != *next_nonce {
+                        problems.push((Severity::Error, ChainError::TransactionFailed {
+                            block: block_num,
+                            transaction: transaction_num,
+                            account: transaction.from.clone(),
+                            error: TransactionError::NonceMismatch {
+                                account: transaction.from.clone(),
+                                expected: *next_nonce,
+                                actual: transaction.nonce,
+                            },
+                        }));
+                    }
+
+                    // A replayed nonce doesn't move on, a skipped one continues after the gap
+                    // (so a single bad transaction doesn't flag all following ones)
+                    if transaction.nonce >= *next_nonce {
+                        *next_nonce = transaction.nonce + 1;
+                    }
+                }
+
+                // Every new account starts with nonce 0, no matter how it was created
+                match &transaction.record {
+                    TransactionData::CreateUserAccount(account)
+                    | TransactionData::CreateAccount { account, .. }
+                    | TransactionData::DeployContract { account, .. } => {
+                        next_nonces.entry(account.clone()).or_insert(0);
+                    }
+                    _ => {}
+                }
+
+                // Careful! Unless the chain requires signatures, an unsigned message will always
+                // be valid! (see `set_require_signatures`) The coinbase needs no signature at all
+                if !transaction.is_coinbase() && !transaction.check_signature() {
+                    let severity = if self.requires_signatures_at(block_num) || transaction.is_signed() {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    };
+
+                    problems.push((severity, ChainError::InvalidSignature {
+                        block: block_num,
+                        transaction: transaction_num,
+                        account: transaction.from.clone(),
+                    }));
+                }
+            }
+
+            // A block can only be trusted if all blocks before it can be trusted
+            let trusted = problems.iter().all(|(severity, _)| *severity == Severity::Warning);
+            if trusted && last_trusted_height.map_or(block_num == 0, |height| height + 1 == block_num) {
+                last_trusted_height = Some(block_num);
+            }
+
+            findings.extend(problems.into_iter()
+                .map(|(severity, error)| Finding { severity, block: block_num, error }));
+        }
+
+        AuditReport { findings, last_trusted_height }
+    }
+
+    /// Rebuilds the world state by executing every transaction from the genesis block on
+    /// and compares it to the accounts of the chain
+    /// (catches overspends within the blocks as well as edited accounts)
+    pub fn verify_state(&self) -> StateReport {
+        let mut state: HashMap<String, Account> = HashMap::new();
+        let mut failed_transaction = None;
+
+        for (block_num, block) in self.blocks.iter().enumerate() {
+            if let Err(err) = block.execute(&mut state, &self.params, block_num) {
+                failed_transaction = Some(err);
+                break;
+            }
+        }
+
+        let mut ids: Vec<&String> = state.keys().chain(self.accounts.keys()).collect();
+        ids.sort();
+        ids.dedup();
+
+        let differences = ids.into_iter()
+            .filter_map(|id| {
+                let stored = self.accounts.get(id);
+                let replayed = state.get(id).cloned().map(|mut account| {
+                    if let Some(stored) = stored {
+                        keep_rejected_blocks(stored, &mut account);
+                    }
+                    account
+                });
+                // Every other field counts (a reset nonce would allow to replay transactions)
+                if replayed.is_some() && replayed.as_ref() == stored {
+                    return None;
+                }
+
+                Some(AccountDifference {
+                    account: id.clone(),
+                    replayed,
+                    stored: stored.cloned(),
+                })
+            })
+            .collect();
+
+        StateReport { failed_transaction, differences }
+    }
+
+    /// Like `check_validity`, but also replays all transactions (see `verify_state`)
+    /// This is expensive, since the whole world state is built up again
+    pub fn check_full_validity(&self) -> Result<(), ChainError> {
+        self.check_validity()?;
+
+        let report = self.verify_state();
+        if let Some(err) = report.failed_transaction {
+            return Err(err);
+        }
+
+        match report.differences.into_iter().next() {
+            Some(difference) => Err(ChainError::StateMismatch { account: difference.account }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The result of replaying every transaction of the chain (see `Blockchain::verify_state`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateReport {
+    /// The first transaction which can't be executed (the replay stops there)
+    pub failed_transaction: Option<ChainError>,
+
+    /// Every account whose balance, store or code differs from the replayed one (ordered by id)
+    pub differences: Vec<AccountDifference>,
+}
+
+/// An account which is not the same after replaying the chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountDifference {
+    pub account: String,
+
+    /// The account as the replay created it (None if the replay didn't create it)
+    pub replayed: Option<Account>,
+
+    /// The account as stored within the chain (None if it is missing)
+    pub stored: Option<Account>,
+}
+
+impl StateReport {
+    /// Will tell if the replay succeeded and led to exactly the stored accounts
+    pub fn is_valid(&self) -> bool {
+        self.failed_transaction.is_none() && self.differences.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{AccountType, Block, ChainSpec, Transaction};
+    use super::*;
+
+    fn chain() -> Blockchain {
+        let mut bc = Blockchain::new();
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new("alice".into(),
+                                                 TransactionData::CreateUserAccount("alice".into()), 0));
+        genesis.mine(0);
+        bc.append_block(genesis).unwrap();
+
+        for nonce in 0..2 {
+            let mut block = Block::new(bc.get_last_block_hash());
+            block.add_transaction(Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+                key: "k".into(), value: nonce.to_string() }, nonce));
+            block.mine(0);
+            bc.append_block(block).unwrap();
+        }
+
+        bc
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let mut bc = chain();
+        assert!(bc.audit().is_valid());
+        assert_eq!(bc.audit().last_trusted_height, Some(2));
+
+        // Tamper the nonce of the second block, which breaks the link and the nonces of the third block
+        bc.blocks[1].transactions[0].nonce = 5;
+        bc.blocks[1].update_hash();
+
+        let report = bc.audit();
+        let codes: Vec<u64> = report.errors().map(|finding| finding.error.code()).collect();
+        assert_eq!(codes, vec![38203984, 3948230, 38203984]);
+        assert_eq!(report.last_trusted_height, Some(0));
+        assert_eq!(report.findings.iter().filter(|finding| finding.severity == Severity::Warning).count(), 3);
+        assert_eq!(bc.check_validity(), Err(report.errors().next().unwrap().error.clone()));
+
+        // Without updating the hash, the edit shows at the block itself
+        let mut bc = chain();
+        bc.blocks[2].transactions[0].nonce = 5;
+        assert_eq!(bc.check_validity(), Err(ChainError::StoredHashMismatch { block: 2 }));
+    }
+
+    #[test]
+    fn replay_finds_edited_accounts_and_overspends() {
+        let mut bc = chain();
+        assert!(bc.verify_state().is_valid());
+
+        // Somebody edited the world state directly
+        bc.accounts.get_mut("alice").unwrap().tokens = 1_000;
+        let report = bc.verify_state();
+        assert_eq!(report.failed_transaction, None);
+        assert_eq!(report.differences.len(), 1);
+        assert_eq!(report.differences[0].stored.as_ref().unwrap().get_tokens(), 1_000);
+        assert_eq!(bc.check_full_validity().map_err(|err| err.code()), Err(665234235));
+
+
+        // ... and sneaked an overspend into the chain (append_block would have rejected it)
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "alice".into(), amount: 1 }, 2));
+        block.mine(0);
+        bc.blocks.push(block);
+
+        assert!(bc.check_validity().is_ok());
+        assert_eq!(bc.verify_state().failed_transaction.map(|err| err.code()), Some(38203984));
+
+        // A nonce set back (which would allow replays) or another account type is found as well
+        let mut bc = chain();
+        bc.accounts.get_mut("alice").unwrap().nonce = 0;
+        assert_eq!(bc.check_full_validity(), Err(ChainError::StateMismatch { account: "alice".into() }));
+
+        let mut bc = chain();
+        bc.accounts.get_mut("alice").unwrap().acc_type = AccountType::Contract;
+        assert_eq!(bc.verify_state().differences.len(), 1);
+    }
+
+    #[test]
+    fn checks_nonces_of_accounts_created_by_a_spec() {
+        let spec = ChainSpec::parse("chain_id = \"audit\"\n[accounts.alice]\nbalance = 10\n[accounts.bob]\n").unwrap();
+        let mut bc = Blockchain::from_spec(&spec).unwrap();
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 1 }, 0));
+        block.mine(0);
+        bc.append_block(block).unwrap();
+        assert!(bc.check_validity().is_ok());
+
+        // bob replays alice's transfer (without append_block, which would reject it)
+        let mut replay = Block::new(bc.get_last_block_hash());
+        replay.add_transaction(bc.blocks[1].transactions[0].clone());
+        replay.mine(0);
+        bc.blocks.push(replay);
+
+        assert_eq!(bc.check_validity().err(), Some(ChainError::TransactionFailed {
+            block: 2,
+            transaction: 0,
+            account: "alice".into(),
+            error: TransactionError::NonceMismatch { account: "alice".into(), expected: 1, actual: 0 },
+        }));
+    }
+}
@@ -0,0 +1,286 @@
+//! Proof of Authority: only known validators produce blocks
+//!
+//! On chains with `ChainParams::proof_of_authority` set, the validator set consists of all
+//! accounts of type `AccountType::Validator`, ordered by their ids (which have to be the hex
+//! encoded public keys). The validators take turns by height: the block at height `h` has to be
+//! produced by validator `h % n`. The producing validator is named within the header (see
+//! `Block::set_validator`) and signs the block hash (see `Block::sign`), so nobody else can
+//! produce a block in its place. Only the genesis block (which creates the validators) is exempt.
+//! Since only the genesis block may create accounts of that type, the validator set never changes
+//! (which allows a `LightClient` to check the blocks without any world state).
+//!
+//! Every accepted block increases the `correctly_validated_blocks` of its validator. A block
+//! which is rejected although the validator in charge signed it increases its
+//! `incorrectly_validated_blocks` (a block signed by anyone else proves nothing about the validator).
+
+use super::{Account, AccountType, Block, BlockHeader, Blockchain, ChainError, Keypair, WorldState, verify_signature};
+
+/// Will return the ids of all validators (sorted, which is the order they take turns in)
+pub(super) fn validators<T: WorldState>(world_state: &T) -> Vec<String> {
+    let mut validators: Vec<String> = world_state.get_user_ids().into_iter()
+        .filter(|id| matches!(world_state.get_account_by_id(id).map(|account| account.get_account_type()),
+                              Some(AccountType::Validator { .. })))
+        .collect();
+    validators.sort();
+    validators
+}
+
+/// Checks if the header at `height` names the validator whose turn it is
+/// and if the signature of its block is made by that validator
+pub(super) fn check_validator(header: &BlockHeader, signature: Option<&String>, validators: &[String],
+                              height: usize) -> Result<(), ChainError> {
+    if validators.is_empty() {
+        return Err(ChainError::NoValidators { block: height });
+    }
+
+    let expected = &validators[height % validators.len()];
+    if header.validator.as_ref() != Some(expected) {
+        return Err(ChainError::WrongValidator {
+            block: height,
+            expected: expected.clone(),
+            actual: header.validator.clone(),
+        });
+    }
+
+    if !header.check_signature(signature) {
+        return Err(ChainError::InvalidBlockSignature { block: height, validator: expected.clone() });
+    }
+
+    Ok(())
+}
+
+/// Checks if the block at `height` is produced and signed by the validator whose turn it is
+/// and credits the block to that validator
+pub(super) fn accept_validator<T: WorldState>(block: &Block, world_state: &mut T,
+                                              height: usize) -> Result<(), ChainError> {
+    let validators = validators(world_state);
+    check_validator(&block.header, block.signature.as_ref(), &validators, height)?;
+
+    let expected = &validators[height % validators.len()];
+    if let Some(AccountType::Validator { correctly_validated_blocks, .. }) =
+        world_state.get_account_by_id_mut(expected).map(|account| &mut account.acc_type) {
+        *correctly_validated_blocks += 1;
+    }
+
+    Ok(())
+}
+
+/// Will return the validator in charge at `height`, if the block is signed by that validator
+pub(super) fn scheduled_signer<T: WorldState>(block: &Block, world_state: &T, height: usize) -> Option<String> {
+    let validators = validators(world_state);
+    check_validator(&block.header, block.signature.as_ref(), &validators, height).ok()?;
+
+    Some(validators[height % validators.len()].clone())
+}
+
+/// Counts a rejected block against the validator (see `scheduled_signer`)
+pub(super) fn blame_validator<T: WorldState>(validator: &str, world_state: &mut T) {
+    if let Some(AccountType::Validator { incorrectly_validated_blocks, .. }) =
+        world_state.get_account_by_id_mut(validator).map(|account| &mut account.acc_type) {
+        *incorrectly_validated_blocks += 1;
+    }
+}
+
+/// Will copy the count of rejected blocks from one version of a validator account to another
+/// (no block records them, so replaying or undoing blocks must not change the count)
+pub(super) fn keep_rejected_blocks(from: &Account, to: &mut Account) {
+    if let (AccountType::Validator { incorrectly_validated_blocks: count, .. },
+            AccountType::Validator { incorrectly_validated_blocks, .. }) = (&from.acc_type, &mut to.acc_type) {
+        *incorrectly_validated_blocks = *count;
+    }
+}
+
+impl Block {
+    /// Will name the validator producing the block (has to be done before mining)
+    pub fn set_validator(&mut self, validator: Option<String>) {
+        self.header.validator = validator;
+        self.signature = None;
+        self.update_hash();
+    }
+
+    /// Returns the validator producing the block (if named)
+    pub fn get_validator(&self) -> Option<&String> {
+        self.header.validator.as_ref()
+    }
+
+    /// Will sign the hash of the block with the given key (has to be done after mining)
+    /// The key has to belong to the validator named in the header
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), ChainError> {
+        let validator = keypair.account_id();
+        if self.header.validator.as_ref() != Some(&validator) {
+            return Err(ChainError::ForeignKey { account: validator });
+        }
+
+        self.signature = Some(keypair.sign(self.calculate_hash().as_bytes()));
+        Ok(())
+    }
+
+    /// Will name the owner of the key as validator, mine the block and sign it
+    pub fn seal(&mut self, keypair: &Keypair, difficulty: u32) {
+        self.set_validator(Some(keypair.account_id()));
+        self.mine(difficulty);
+        self.sign(keypair).expect("The key belongs to the validator");
+    }
+
+    /// Will check if the block is signed by the validator named in the header
+    /// (always false if there is no validator or no signature)
+    pub fn check_signature(&self) -> bool {
+        self.header.check_signature(self.signature.as_ref())
+    }
+
+    /// Returns the signature of the validator (hex encoded)
+    pub fn get_signature(&self) -> Option<&String> {
+        self.signature.as_ref()
+    }
+}
+
+impl BlockHeader {
+    /// Will check if the signature (of the block) is made by the validator named in the header
+    /// (always false if there is no validator or no signature)
+    pub fn check_signature(&self, signature: Option<&String>) -> bool {
+        match (&self.validator, signature) {
+            (Some(validator), Some(signature)) =>
+                verify_signature(validator, self.calculate_hash().as_bytes(), signature),
+            _ => false,
+        }
+    }
+}
+
+impl Blockchain {
+    /// Will return the ids of all validators (in the order they take turns in)
+    pub fn get_validators(&self) -> Vec<String> {
+        validators(&self.accounts)
+    }
+
+    /// Will return the validator which has to produce the next block
+    /// (None unless the chain uses Proof of Authority and has validators)
+    pub fn next_validator(&self) -> Option<String> {
+        if !self.params.proof_of_authority || self.is_empty() {
+            return None;
+        }
+
+        let validators = self.get_validators();
+        if validators.is_empty() {
+            return None;
+        }
+
+        Some(validators[self.len() % validators.len()].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ChainSpec, Transaction, TransactionData};
+    use super::*;
+
+    /// A chain with two validators (sorted by id) and a user
+    fn chain() -> (Blockchain, Vec<Keypair>) {
+        let mut keys = vec![Keypair::generate(), Keypair::generate()];
+        keys.sort_by_key(|key| key.account_id());
+
+        let spec = ChainSpec::parse(&format!("chain_id = \"poa\"\nproof_of_authority = true\n\
+                                              [accounts.{}]\ntype = \"validator\"\n\
+                                              [accounts.{}]\ntype = \"validator\"\n\
+                                              [accounts.alice]\nbalance = 10\n",
+                                             keys[0].account_id(), keys[1].account_id())).unwrap();
+        (Blockchain::from_spec(&spec).unwrap(), keys)
+    }
+
+    fn block(bc: &Blockchain) -> Block {
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+            key: "height".into(), value: bc.len().to_string() }, bc.len() as u128 - 1));
+        block
+    }
+
+    fn blocks_of(bc: &Blockchain, key: &Keypair) -> u128 {
+        match bc.get_account_by_id(&key.account_id()).unwrap().get_account_type() {
+            AccountType::Validator { correctly_validated_blocks, .. } => *correctly_validated_blocks,
+            _ => panic!("not a validator"),
+        }
+    }
+
+    fn rejected_blocks_of(bc: &Blockchain, key: &Keypair) -> u128 {
+        match bc.get_account_by_id(&key.account_id()).unwrap().get_account_type() {
+            AccountType::Validator { incorrectly_validated_blocks, .. } => *incorrectly_validated_blocks,
+            _ => panic!("not a validator"),
+        }
+    }
+
+    #[test]
+    fn validators_take_turns() {
+        let (mut bc, keys) = chain();
+        assert_eq!(bc.get_validators(), vec![keys[0].account_id(), keys[1].account_id()]);
+
+        for height in 1..=3 {
+            let key = &keys[height % 2];
+            assert_eq!(bc.next_validator(), Some(key.account_id()));
+
+            let mut block = block(&bc);
+            block.seal(key, 0);
+            bc.append_block(block).unwrap();
+        }
+
+        assert_eq!(blocks_of(&bc, &keys[1]), 2);
+        assert_eq!(blocks_of(&bc, &keys[0]), 1);
+        assert!(bc.check_full_validity().is_ok());
+    }
+
+    #[test]
+    fn rejects_foreign_and_badly_signed_blocks() {
+        let (mut bc, keys) = chain();
+
+        // Not its turn
+        let mut wrong = block(&bc);
+        wrong.seal(&keys[0], 0);
+        assert_eq!(bc.append_block(wrong).err(), Some(ChainError::WrongValidator {
+            block: 1, expected: keys[1].account_id(), actual: Some(keys[0].account_id()) }));
+
+        // No validator at all
+        let mut unsealed = block(&bc);
+        unsealed.mine(0);
+        assert_eq!(bc.append_block(unsealed).err().map(|err| err.code()), Some(8820391));
+
+        // Named, but not signed (or signed by somebody else)
+        let mut unsigned = block(&bc);
+        unsigned.set_validator(Some(keys[1].account_id()));
+        unsigned.mine(0);
+        assert!(unsigned.sign(&keys[0]).is_err());
+        assert_eq!(bc.append_block(unsigned.clone()).err(), Some(ChainError::InvalidBlockSignature {
+            block: 1, validator: keys[1].account_id() }));
+
+        unsigned.signature = Some(keys[0].sign(unsigned.calculate_hash().as_bytes()));
+        assert_eq!(bc.append_block(unsigned).err().map(|err| err.code()), Some(8820392));
+
+        // Rejected blocks don't count, and blocks not signed by the validator in charge prove nothing
+        assert_eq!(blocks_of(&bc, &keys[1]), 0);
+        assert_eq!(rejected_blocks_of(&bc, &keys[1]), 0);
+        assert_eq!(rejected_blocks_of(&bc, &keys[0]), 0);
+        assert_eq!(bc.len(), 1);
+    }
+
+    #[test]
+    fn counts_rejected_blocks_of_the_validator_in_charge() {
+        let (mut bc, keys) = chain();
+
+        let mut replay = Block::new(bc.get_last_block_hash());
+        replay.add_transaction(Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+            key: "k".into(), value: "v".into() }, 5));
+        replay.seal(&keys[1], 0);
+        assert_eq!(bc.append_block(replay).err().map(|err| err.code()), Some(38203984));
+        assert_eq!(rejected_blocks_of(&bc, &keys[1]), 1);
+        assert_eq!(rejected_blocks_of(&bc, &keys[0]), 0);
+
+        let mut accepted = block(&bc);
+        accepted.seal(&keys[1], 0);
+        bc.append_block(accepted).unwrap();
+        assert_eq!(blocks_of(&bc, &keys[1]), 1);
+        assert_eq!(rejected_blocks_of(&bc, &keys[1]), 1);
+
+        // No block records the rejection, so neither the replay nor a rewind forgets it
+        assert!(bc.check_full_validity().is_ok());
+        bc.rewind_to(0).unwrap();
+        assert_eq!(blocks_of(&bc, &keys[1]), 0);
+        assert_eq!(rejected_blocks_of(&bc, &keys[1]), 1);
+    }
+}
@@ -0,0 +1,595 @@
+//! Smart contracts: bytecode which is run by a small stack machine
+//!
+//! A contract is an account of type `AccountType::Contract` holding bytecode (see
+//! `TransactionData::DeployContract`). Every `TransactionData::CallContract` runs the code
+//! from the first instruction on. The machine only sees the contract itself and the call:
+//! there is no clock, no randomness and no I/O, so every node gets the very same result.
+//!
+//! Every value on the stack is a string. Instructions working with numbers parse them as
+//! decimal `u128` (and fail on anything else or on overflow). Conditions treat `""` and `"0"`
+//! as false, comparisons push `"1"` or `"0"`.
+//!
+//! While running, the contract works on a copy of its store and tokens. Only if it stops
+//! without error the changes (and the token transfers) are applied, so a failing call
+//! leaves no trace but the gas it used (see `gas`). Every instruction costs gas
+//! (see `Instruction::gas_cost`), so every call terminates.
+
+use super::{Account, AccountType, TransactionError, WorldState, MAX_STORE_VALUE_LENGTH};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+
+/// Maximum size of the bytecode of a contract (in bytes)
+pub const MAX_CODE_LENGTH: usize = 16 * 1024;
+
+/// Maximum amount of values on the stack
+pub const MAX_STACK_DEPTH: usize = 256;
+
+/// A single instruction of the stack machine
+/// (`a` is the topmost value of the stack, `b` the one below)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// Pushes the value
+    Push(String),
+
+    /// Removes `a`
+    Pop,
+
+    /// Pushes a copy of the value at the given depth (0 is `a`)
+    Dup(u8),
+
+    /// Exchanges `a` and `b`
+    Swap,
+
+    /// Replace `b` and `a` with `b + a`, `b - a`, `b * a`, `b / a` or `b % a`
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+
+    /// Replace `b` and `a` with `b == a` (compared as strings), `b < a` or `b > a` (as numbers)
+    Eq,
+    Lt,
+    Gt,
+
+    /// Replaces `a` with its negation
+    Not,
+
+    /// Replaces `b` and `a` with `b` followed by `a`
+    Concat,
+
+    /// Continues at the instruction with the given index
+    Jump(u32),
+
+    /// Removes `a` and continues at the instruction with the given index if `a` is true
+    JumpIf(u32),
+
+    /// Replaces the key `a` with its value within the store of the contract (`""` if missing)
+    Load,
+
+    /// Removes the key `a` and the value `b` and stores them (an empty value deletes the key)
+    Store,
+
+    /// Pushes the argument of the call with the given index (`""` if missing)
+    Arg(u8),
+
+    /// Pushes the amount of arguments
+    ArgCount,
+
+    /// Pushes the account id of the caller
+    Caller,
+
+    /// Pushes the amount of tokens sent along with the call
+    CallValue,
+
+    /// Pushes the tokens of the contract (including the ones sent along)
+    Balance,
+
+    /// Pushes the account id of the contract
+    Address,
+
+    /// Removes the receiver `a` and the amount `b` and sends the tokens of the contract there
+    Transfer,
+
+    /// Ends the call successfully (same as reaching the end of the code)
+    Stop,
+
+    /// Ends the call with an error
+    Revert,
+}
+
+/// Reasons why a contract call fails
+/// `position` is the index of the failing instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContractError {
+    /// More values are needed than there are on the stack
+    StackUnderflow { position: usize },
+
+    /// The stack exceeds `MAX_STACK_DEPTH`
+    StackOverflow { position: usize },
+
+    /// A value is not a decimal number
+    NotANumber { position: usize },
+
+    /// The result of a calculation doesn't fit into a `u128`
+    Overflow { position: usize },
+
+    DivisionByZero { position: usize },
+
+    /// A value exceeds `MAX_STORE_VALUE_LENGTH`
+    ValueTooLong { position: usize },
+
+    /// The jump target is not an instruction of the contract
+    InvalidJump { position: usize },
+
+    /// Storing the value breaks a limit of the store (see `MAX_STORE_ENTRIES`)
+    StoreRejected { position: usize },
+
+    /// The receiver of a transfer does not exist
+    ReceiverNotFound { position: usize, account: String },
+
+    /// The contract doesn't have enough tokens for a transfer
+    Overspent { position: usize },
+
+    /// The contract ended the call with `Revert`
+    Reverted { position: usize },
+
+    /// The gas left is not enough for the instruction (all of it is used up)
+    OutOfGas { position: usize },
+}
+
+impl fmt::Display for ContractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContractError::StackUnderflow { position } =>
+                write!(f, "Stack underflow at instruction {}", position),
+            ContractError::StackOverflow { position } =>
+                write!(f, "Stack overflow at instruction {}", position),
+            ContractError::NotANumber { position } =>
+                write!(f, "Value is not a number at instruction {}", position),
+            ContractError::Overflow { position } =>
+                write!(f, "Arithmetic overflow at instruction {}", position),
+            ContractError::DivisionByZero { position } =>
+                write!(f, "Division by zero at instruction {}", position),
+            ContractError::ValueTooLong { position } =>
+                write!(f, "Value too long at instruction {}", position),
+            ContractError::InvalidJump { position } =>
+                write!(f, "Invalid jump target at instruction {}", position),
+            ContractError::StoreRejected { position } =>
+                write!(f, "Store rejected the value at instruction {}", position),
+            ContractError::ReceiverNotFound { position, account } =>
+                write!(f, "Receiver `{}` not found at instruction {}", account, position),
+            ContractError::Overspent { position } =>
+                write!(f, "Contract overspent at instruction {}", position),
+            ContractError::Reverted { position } =>
+                write!(f, "Reverted at instruction {}", position),
+            ContractError::OutOfGas { position } =>
+                write!(f, "Out of gas at instruction {}", position),
+        }
+    }
+}
+
+impl std::error::Error for ContractError {}
+
+/// Will encode the instructions as bytecode
+/// Every instruction is its opcode (`u8`) followed by its operand (if any): strings are
+/// the length (`u32`) followed by the UTF-8 bytes, indices are `u8` or `u32` (little endian)
+pub fn encode_code(instructions: &[Instruction]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for instruction in instructions {
+        match instruction {
+            Instruction::Push(value) => {
+                out.push(0x01);
+                out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                out.extend_from_slice(value.as_bytes());
+            }
+            Instruction::Pop => out.push(0x02),
+            Instruction::Dup(depth) => out.extend_from_slice(&[0x03, *depth]),
+            Instruction::Swap => out.push(0x04),
+            Instruction::Add => out.push(0x10),
+            Instruction::Sub => out.push(0x11),
+            Instruction::Mul => out.push(0x12),
+            Instruction::Div => out.push(0x13),
+            Instruction::Mod => out.push(0x14),
+            Instruction::Eq => out.push(0x18),
+            Instruction::Lt => out.push(0x19),
+            Instruction::Gt => out.push(0x1a),
+            Instruction::Not => out.push(0x1b),
+            Instruction::Concat => out.push(0x1c),
+            Instruction::Jump(target) => {
+                out.push(0x20);
+                out.extend_from_slice(&target.to_le_bytes());
+            }
+            Instruction::JumpIf(target) => {
+                out.push(0x21);
+                out.extend_from_slice(&target.to_le_bytes());
+            }
+            Instruction::Load => out.push(0x30),
+            Instruction::Store => out.push(0x31),
+            Instruction::Arg(index) => out.extend_from_slice(&[0x38, *index]),
+            Instruction::ArgCount => out.push(0x39),
+            Instruction::Caller => out.push(0x40),
+            Instruction::CallValue => out.push(0x41),
+            Instruction::Balance => out.push(0x42),
+            Instruction::Address => out.push(0x43),
+            Instruction::Transfer => out.push(0x48),
+            Instruction::Stop => out.push(0x50),
+            Instruction::Revert => out.push(0x51),
+        }
+    }
+
+    out
+}
+
+/// Will decode bytecode written by `encode_code`
+/// Returns None if the code is malformed or longer than `MAX_CODE_LENGTH`
+pub fn decode_code(mut code: &[u8]) -> Option<Vec<Instruction>> {
+    if code.len() > MAX_CODE_LENGTH {
+        return None;
+    }
+
+    let mut instructions = Vec::new();
+    while let Some((&opcode, rest)) = code.split_first() {
+        code = rest;
+
+        let mut take = |length: usize| -> Option<&[u8]> {
+            if code.len() < length {
+                return None;
+            }
+            let (operand, rest) = code.split_at(length);
+            code = rest;
+            Some(operand)
+        };
+
+        let instruction = match opcode {
+            0x01 => {
+                let length = u32::from_le_bytes(take(4)?.try_into().ok()?) as usize;
+                Instruction::Push(String::from_utf8(take(length)?.to_vec()).ok()?)
+            }
+            0x02 => Instruction::Pop,
+            0x03 => Instruction::Dup(take(1)?[0]),
+            0x04 => Instruction::Swap,
+            0x10 => Instruction::Add,
+            0x11 => Instruction::Sub,
+            0x12 => Instruction::Mul,
+            0x13 => Instruction::Div,
+            0x14 => Instruction::Mod,
+            0x18 => Instruction::Eq,
+            0x19 => Instruction::Lt,
+            0x1a => Instruction::Gt,
+            0x1b => Instruction::Not,
+            0x1c => Instruction::Concat,
+            0x20 => Instruction::Jump(u32::from_le_bytes(take(4)?.try_into().ok()?)),
+            0x21 => Instruction::JumpIf(u32::from_le_bytes(take(4)?.try_into().ok()?)),
+            0x30 => Instruction::Load,
+            0x31 => Instruction::Store,
+            0x38 => Instruction::Arg(take(1)?[0]),
+            0x39 => Instruction::ArgCount,
+            0x40 => Instruction::Caller,
+            0x41 => Instruction::CallValue,
+            0x42 => Instruction::Balance,
+            0x43 => Instruction::Address,
+            0x48 => Instruction::Transfer,
+            0x50 => Instruction::Stop,
+            0x51 => Instruction::Revert,
+            _ => return None,
+        };
+        instructions.push(instruction);
+    }
+
+    Some(instructions)
+}
+
+/// What a successful call changes (applied by `Effects::apply`)
+pub(super) struct Effects {
+    /// The new store of the contract
+    store: HashMap<String, String>,
+
+    /// The new amount of tokens of the contract
+    tokens: u128,
+
+    /// Tokens sent to other accounts
+    transfers: Vec<(String, u128)>,
+}
+
+impl Effects {
+    /// Will write the changes into the world state (the value of the call has to be taken from
+    /// the caller already)
+    pub(super) fn apply<T: WorldState>(self, world_state: &mut T, contract: &str) {
+        if let Some(account) = world_state.get_account_by_id_mut(contract) {
+            account.store = self.store;
+            account.tokens = self.tokens;
+        }
+
+        for (receiver, amount) in self.transfers {
+            if let Some(account) = world_state.get_account_by_id_mut(&receiver) {
+                account.tokens = account.tokens.saturating_add(amount);
+            }
+        }
+    }
+}
+
+/// Everything the contract knows about the call
+pub(super) struct Call<'a> {
+    pub caller: &'a str,
+    pub contract: &'a str,
+    pub value: u128,
+    pub args: &'a [String],
+}
+
+/// Will run the code of the contract without changing the world state
+/// The contract has to exist and the value has to be affordable for the caller
+/// `gas` is the gas available, the gas of every executed instruction is taken from it
+pub(super) fn run<T: WorldState>(world_state: &T, call: &Call, gas: &mut u64) -> Result<Effects, ContractError> {
+    let account: &Account = world_state.get_account_by_id(call.contract)
+        .expect("The contract is checked by the caller");
+    let instructions = decode_code(&account.code).unwrap_or_default();
+
+    // The value is part of the balance right from the start (unless the contract calls itself)
+    let mut effects = Effects {
+        store: account.store.clone(),
+        tokens: if call.caller == call.contract {
+            account.tokens
+        } else {
+            account.tokens.checked_add(call.value).ok_or(ContractError::Overflow { position: 0 })?
+        },
+        transfers: Vec::new(),
+    };
+
+    let mut stack: Vec<String> = Vec::new();
+    let mut position = 0;
+
+    while let Some(instruction) = instructions.get(position) {
+        *gas = match gas.checked_sub(instruction.gas_cost()) {
+            Some(left) => left,
+            None => {
+                *gas = 0;
+                return Err(ContractError::OutOfGas { position });
+            }
+        };
+
+        let mut pop = || stack.pop().ok_or(ContractError::StackUnderflow { position });
+        let number = |value: String| value.parse::<u128>().map_err(|_| ContractError::NotANumber { position });
+        let flag = |value: bool| if value { "1".to_string() } else { "0".to_string() };
+
+        let mut next = position + 1;
+        let pushed = match instruction {
+            Instruction::Push(value) => Some(value.clone()),
+            Instruction::Pop => {
+                pop()?;
+                None
+            }
+            Instruction::Dup(depth) => {
+                let index = stack.len().checked_sub(*depth as usize + 1)
+                    .ok_or(ContractError::StackUnderflow { position })?;
+                Some(stack[index].clone())
+            }
+            Instruction::Swap => {
+                let a = pop()?;
+                let b = pop()?;
+                stack.push(a);
+                Some(b)
+            }
+            Instruction::Add | Instruction::Sub | Instruction::Mul | Instruction::Div | Instruction::Mod
+            | Instruction::Lt | Instruction::Gt => {
+                let a = number(pop()?)?;
+                let b = number(pop()?)?;
+                let result = match instruction {
+                    Instruction::Add => b.checked_add(a).ok_or(ContractError::Overflow { position })?,
+                    Instruction::Sub => b.checked_sub(a).ok_or(ContractError::Overflow { position })?,
+                    Instruction::Mul => b.checked_mul(a).ok_or(ContractError::Overflow { position })?,
+                    Instruction::Div => b.checked_div(a).ok_or(ContractError::DivisionByZero { position })?,
+                    Instruction::Mod => b.checked_rem(a).ok_or(ContractError::DivisionByZero { position })?,
+                    Instruction::Lt => (b < a) as u128,
+                    _ => (b > a) as u128,
+                };
+                Some(result.to_string())
+            }
+            Instruction::Eq => {
+                let a = pop()?;
+                let b = pop()?;
+                Some(flag(a == b))
+            }
+            Instruction::Not => Some(flag(!is_true(&pop()?))),
+            Instruction::Concat => {
+                let a = pop()?;
+                let b = pop()?;
+                Some(b + &a)
+            }
+            Instruction::Jump(target) => {
+                next = *target as usize;
+                None
+            }
+            Instruction::JumpIf(target) => {
+                if is_true(&pop()?) {
+                    next = *target as usize;
+                }
+                None
+            }
+            Instruction::Load => {
+                let key = pop()?;
+                Some(effects.store.get(&key).cloned().unwrap_or_default())
+            }
+            Instruction::Store => {
+                let key = pop()?;
+                let value = pop()?;
+                Account::set_store_value(&mut effects.store, call.contract, &key, &value)
+                    .map_err(|_| ContractError::StoreRejected { position })?;
+                None
+            }
+            Instruction::Arg(index) => Some(call.args.get(*index as usize).cloned().unwrap_or_default()),
+            Instruction::ArgCount => Some(call.args.len().to_string()),
+            Instruction::Caller => Some(call.caller.to_string()),
+            Instruction::CallValue => Some(call.value.to_string()),
+            Instruction::Balance => Some(effects.tokens.to_string()),
+            Instruction::Address => Some(call.contract.to_string()),
+            Instruction::Transfer => {
+                let receiver = pop()?;
+                let amount = number(pop()?)?;
+                if world_state.get_account_by_id(&receiver).is_none() {
+                    return Err(ContractError::ReceiverNotFound { position, account: receiver });
+                }
+
+                effects.tokens = effects.tokens.checked_sub(amount).ok_or(ContractError::Overspent { position })?;
+                if receiver == call.contract {
+                    effects.tokens += amount;
+                } else {
+                    effects.transfers.push((receiver, amount));
+                }
+                None
+            }
+            Instruction::Stop => break,
+            Instruction::Revert => return Err(ContractError::Reverted { position }),
+        };
+
+        if let Some(value) = pushed {
+            if value.len() > MAX_STORE_VALUE_LENGTH {
+                return Err(ContractError::ValueTooLong { position });
+            }
+            if stack.len() >= MAX_STACK_DEPTH {
+                return Err(ContractError::StackOverflow { position });
+            }
+            stack.push(value);
+        }
+
+        // Jumping right behind the last instruction ends the call like reaching the end
+        if next > instructions.len() {
+            return Err(ContractError::InvalidJump { position });
+        }
+        position = next;
+    }
+
+    Ok(effects)
+}
+
+fn is_true(value: &str) -> bool {
+    !value.is_empty() && value != "0"
+}
+
+/// Will create the contract account (see `TransactionData::DeployContract`)
+pub(super) fn deploy<T: WorldState>(world_state: &mut T, account: &str, code: &[u8]) -> Result<(), TransactionError> {
+    if decode_code(code).is_none() {
+        return Err(TransactionError::InvalidContractCode { account: account.to_string() });
+    }
+
+    world_state.create_account(account.to_string(), AccountType::Contract)?;
+    world_state.get_account_by_id_mut(account).unwrap().code = code.to_vec();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Instruction::*;
+
+    fn call(code: &[Instruction], args: &[&str]) -> (Result<Effects, ContractError>, HashMap<String, Account>) {
+        let mut accounts = HashMap::new();
+        accounts.create_account("alice".into(), AccountType::User).unwrap();
+        deploy(&mut accounts, "counter", &encode_code(code)).unwrap();
+
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut gas = 100_000;
+        let result = run(&accounts, &Call { caller: "alice", contract: "counter", value: 10, args: &args }, &mut gas);
+        (result, accounts)
+    }
+
+    #[test]
+    fn code_round_trip() {
+        let code = vec![Push("hällo".into()), Dup(3), JumpIf(7), Arg(1), Transfer, Revert];
+        assert_eq!(decode_code(&encode_code(&code)), Some(code));
+        assert_eq!(decode_code(&[0x01, 5, 0, 0, 0, b'a']), None);
+        assert_eq!(decode_code(&[0xff]), None);
+    }
+
+    #[test]
+    fn runs_loops_and_transfers() {
+        // Adds up 1..=n (n is the first argument) within the store and pays 3 tokens back
+        let code = vec![
+            Push("0".into()), Push("sum".into()), Store,            // sum = 0
+            Arg(0),                                                 // n
+            Dup(0), Not, JumpIf(16),                                // while n != 0
+            Dup(0), Push("sum".into()), Load, Add,                  // n, sum + n
+            Push("sum".into()), Store,                              // sum = sum + n
+            Push("1".into()), Sub,                                  // n - 1
+            Jump(4),
+            Pop,
+            Push("3".into()), Caller, Transfer,
+        ];
+
+        let (effects, accounts) = call(&code, &["4"]);
+        let effects = effects.unwrap();
+        assert_eq!(effects.store.get("sum"), Some(&"10".to_string()));
+        assert_eq!(effects.tokens, 7);
+        assert_eq!(effects.transfers, vec![("alice".to_string(), 3)]);
+
+        let mut accounts = accounts;
+        effects.apply(&mut accounts, "counter");
+        assert_eq!(accounts.get_store_value("counter", "sum"), Some(&"10".to_string()));
+        assert_eq!(accounts.get_balance("alice"), Some(3));
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        assert_eq!(call(&[Add], &[]).0.err(), Some(ContractError::StackUnderflow { position: 0 }));
+        assert_eq!(call(&[Arg(0), Arg(0), Div], &["0"]).0.err(), Some(ContractError::DivisionByZero { position: 2 }));
+        assert_eq!(call(&[Push("x".into()), Arg(0), Add], &["1"]).0.err(),
+                   Some(ContractError::NotANumber { position: 2 }));
+        assert_eq!(call(&[Push("11".into()), Caller, Transfer], &[]).0.err(),
+                   Some(ContractError::Overspent { position: 2 }));
+        assert_eq!(call(&[Jump(0)], &[]).0.err(), Some(ContractError::OutOfGas { position: 0 }));
+        assert_eq!(call(&[Jump(2)], &[]).0.err(), Some(ContractError::InvalidJump { position: 0 }));
+        assert_eq!(call(&[Push("x".into()), Dup(0), Concat, Jump(1)], &[]).0.err(),
+                   Some(ContractError::ValueTooLong { position: 2 }));
+        assert!(call(&[Jump(2), Revert], &[]).0.is_ok());
+    }
+
+    #[test]
+    fn contracts_on_the_chain() {
+        use super::super::{Block, Blockchain, ChainSpec, Transaction, TransactionData};
+
+        let spec = ChainSpec::parse("chain_id = \"contracts\"\n[accounts.alice]\nbalance = 100\n").unwrap();
+        let mut bc = Blockchain::from_spec(&spec).unwrap();
+
+        // A vault: keeps what it gets and pays out the amount given as argument (only to alice)
+        let code = encode_code(&[
+            Caller, Push("alice".into()), Eq, JumpIf(5), Revert,
+            Arg(0), Caller, Transfer,
+            CallValue, Push("received".into()), Store,
+        ]);
+        bc.submit_transaction(Transaction::new("alice".into(), TransactionData::DeployContract {
+            account: "vault".into(), code }, 0)).unwrap();
+        bc.submit_transaction(Transaction::new("alice".into(), TransactionData::CallContract {
+            contract: "vault".into(), amount: 40, args: vec!["15".into()] }, 1)).unwrap();
+        let mut block = bc.build_block_template();
+        block.mine(0);
+        bc.append_block(block).unwrap();
+
+        assert_eq!(bc.get_balance("vault"), Some(25));
+        assert_eq!(bc.get_balance("alice"), Some(75));
+        assert_eq!(bc.get_store_value("vault", "received"), Some(&"40".to_string()));
+        assert_eq!(bc.get_account_by_id("vault").unwrap().get_account_type(), &AccountType::Contract);
+
+        // Paying out more than the vault has fails, and nothing of the call remains
+        // (the mempool rejects it, within a block only the nonce and the gas are used up)
+        let vault = bc.get_account_by_id("vault").unwrap().clone();
+        let overdraw = Transaction::new("alice".into(), TransactionData::CallContract {
+            contract: "vault".into(), amount: 5, args: vec!["31".into()] }, 2);
+        assert_eq!(bc.submit_transaction(overdraw.clone()).map_err(|err| err.code()), Err(2938473));
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(overdraw);
+        block.mine(0);
+        bc.append_block(block).unwrap();
+
+        let receipt = &bc.get_receipts(2).unwrap()[0];
+        assert_eq!(receipt.error, Some(TransactionError::ContractFailed {
+            account: "vault".into(), error: ContractError::Overspent { position: 7 } }));
+        // The call itself and its argument, then the 7 instructions up to the transfer
+        assert_eq!(receipt.gas_used, 3_020 + 1_016);
+        assert_eq!(bc.get_account_by_id("vault"), Some(&vault));
+        assert_eq!(bc.get_balance("alice"), Some(75));
+        assert_eq!(bc.get_account_by_id("alice").unwrap().get_nonce(), 3);
+        assert!(bc.check_full_validity().is_ok());
+    }
+}
@@ -0,0 +1,543 @@
+//! Canonical binary encoding of the chain types
+//!
+//! Everything that gets hashed (and therefore signed) is encoded with the functions of this
+//! module. In contrast to `format!("{:?}", ..)` the output does not depend on the compiler,
+//! the standard library or the platform, so every build calculates the very same hashes.
+//!
+//! Format (version 11):
+//! - Every encoding starts with the version byte (`ENCODING_VERSION`)
+//! - Integers are fixed width and little endian (`u8`, `u32`, `u64`, `u128`)
+//! - Strings and byte arrays are the length in bytes (`u32`) followed by the (UTF-8) bytes
+//! - Lists are the amount of items (`u32`) followed by the items
+//! - Optional values are a `u8` tag (0 = None, 1 = Some) followed by the value if present
+//! - Points in time are the seconds (`u64`) and the nanoseconds (`u32`) since UNIX epoch
+//! - `TransactionData` is a `u8` tag followed by the fields of the variant in declaration order:
+//!   `0` CreateUserAccount(account), `1` ChangeStoreValue {key, value},
+//!   `2` TransferTokens {to, amount}, `3` CreateTokens {receiver, amount},
+//!   `4` Coinbase {receiver, amount}, `5` CreateAccount {account, account_type},
+//!   `6` ChainInfo {chain_id, params, require_signatures: u8}, `7` DeployContract {account, code: bytes},
+//!   `8` CallContract {contract, amount, args: list of strings}
+//! - `AccountType` is a `u8` tag followed by its fields: `0` User, `1` Contract,
+//!   `2` Validator {correctly_validated_blocks: u128, incorrectly_validated_blocks: u128,
+//!   you_get_the_idea: u8}
+//! - `ChainParams` is `initial_difficulty: u32, retarget_interval: u64, target_block_time: u64,
+//!   initial_reward: u128, halving_interval: u64, block_gas_limit: u64, proof_of_authority: u8`
+//! - `Transaction` is `version, nonce: u128, fee: u128, gas_limit: u64, gas_price: u128,
+//!   from: string, created_at: time, record: TransactionData`
+//!   (the signature is not part of it, since it signs exactly these bytes)
+//! - Hashes are their 32 raw bytes
+//! - A block header is `version, prev_hash: optional hash, nonce: u128, timestamp: u64,
+//!   difficulty: u32, tx_root: hash, validator: optional string` where `tx_root` is the
+//!   Merkle root over all transaction hashes (see `merkle_root`)
+//!   (the signature of the validator is not part of it, since it signs the hash of the header)
+//!
+//! Any change to the format requires a new version number.
+
+use super::{AccountType, Block, BlockHeader, ChainParams, Transaction, TransactionData, Hash, HASH_LENGTH};
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the encoding format, part of every encoded value
+pub const ENCODING_VERSION: u8 = 11;
+
+/// Encodes the transaction data (without version byte, it's always part of a transaction)
+pub fn encode_transaction_data(data: &TransactionData) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_transaction_data(&mut out, data);
+    out
+}
+
+/// Encodes everything of a transaction that is covered by its hash (and signature)
+pub fn encode_transaction(transaction: &Transaction) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION];
+    write_transaction(&mut out, transaction);
+    out
+}
+
+/// Encodes the header of a block (everything that is covered by the block hash)
+pub fn encode_block_header(header: &BlockHeader) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION];
+    write_optional_hash(&mut out, &header.prev_hash);
+    write_u128(&mut out, header.nonce);
+    write_u64(&mut out, header.timestamp);
+    write_u32(&mut out, header.difficulty);
+    out.extend_from_slice(header.tx_root.as_bytes());
+    write_optional_string(&mut out, &header.validator);
+    out
+}
+
+/// Encodes a whole block including transactions, signatures and the stored hash
+/// (used for storing blocks)
+pub(super) fn encode_block(block: &Block) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION];
+    write_optional_hash(&mut out, &block.header.prev_hash);
+    write_optional_hash(&mut out, &block.hash);
+    out.extend_from_slice(block.header.tx_root.as_bytes());
+    write_u128(&mut out, block.header.nonce);
+    write_u64(&mut out, block.header.timestamp);
+    write_u32(&mut out, block.header.difficulty);
+    write_optional_string(&mut out, &block.header.validator);
+    write_optional_string(&mut out, &block.signature);
+
+    write_u32(&mut out, block.transactions.len() as u32);
+    for transaction in block.transactions.iter() {
+        write_transaction(&mut out, transaction);
+        write_optional_string(&mut out, &transaction.signature);
+    }
+
+    out
+}
+
+/// Decodes a block written by `encode_block`
+/// Returns None if the data is malformed or uses another version
+pub(super) fn decode_block(data: &[u8]) -> Option<Block> {
+    let mut reader = Reader { data };
+    if reader.u8()? != ENCODING_VERSION {
+        return None;
+    }
+
+    let prev_hash = reader.optional_hash()?;
+    let hash = reader.optional_hash()?;
+    let tx_root = reader.hash()?;
+    let nonce = reader.u128()?;
+    let timestamp = reader.u64()?;
+    let difficulty = reader.u32()?;
+    let validator = reader.optional_string()?;
+    let signature = reader.optional_string()?;
+
+    let transaction_count = reader.u32()?;
+    let mut transactions = Vec::new();
+    for _ in 0..transaction_count {
+        let mut transaction = reader.transaction()?;
+        transaction.signature = reader.optional_string()?;
+        transactions.push(transaction);
+    }
+
+    // There must not be anything left
+    if !reader.data.is_empty() {
+        return None;
+    }
+
+    Some(Block {
+        header: BlockHeader { prev_hash, tx_root, nonce, timestamp, difficulty, validator },
+        transactions,
+        hash,
+        signature,
+    })
+}
+
+/// Encodes a transaction including its signature (used for storing pending transactions)
+pub(super) fn encode_signed_transaction(transaction: &Transaction) -> Vec<u8> {
+    let mut out = vec![ENCODING_VERSION];
+    write_transaction(&mut out, transaction);
+    write_optional_string(&mut out, &transaction.signature);
+    out
+}
+
+/// Decodes a transaction written by `encode_signed_transaction`
+/// Returns None if the data is malformed or uses another version
+pub(super) fn decode_signed_transaction(data: &[u8]) -> Option<Transaction> {
+    let mut reader = Reader { data };
+    if reader.u8()? != ENCODING_VERSION {
+        return None;
+    }
+
+    let mut transaction = reader.transaction()?;
+    transaction.signature = reader.optional_string()?;
+    if !reader.data.is_empty() {
+        return None;
+    }
+
+    Some(transaction)
+}
+
+fn write_transaction(out: &mut Vec<u8>, transaction: &Transaction) {
+    write_u128(out, transaction.nonce);
+    write_u128(out, transaction.fee);
+    write_u64(out, transaction.gas_limit);
+    write_u128(out, transaction.gas_price);
+    write_string(out, &transaction.from);
+    write_time(out, &transaction.created_at);
+    write_transaction_data(out, &transaction.record);
+}
+
+fn write_transaction_data(out: &mut Vec<u8>, data: &TransactionData) {
+    match data {
+        TransactionData::CreateUserAccount(account) => {
+            out.push(0);
+            write_string(out, account);
+        }
+        TransactionData::ChangeStoreValue { key, value } => {
+            out.push(1);
+            write_string(out, key);
+            write_string(out, value);
+        }
+        TransactionData::TransferTokens { to, amount } => {
+            out.push(2);
+            write_string(out, to);
+            write_u128(out, *amount);
+        }
+        TransactionData::CreateTokens { receiver, amount } => {
+            out.push(3);
+            write_string(out, receiver);
+            write_u128(out, *amount);
+        }
+        TransactionData::Coinbase { receiver, amount } => {
+            out.push(4);
+            write_string(out, receiver);
+            write_u128(out, *amount);
+        }
+        TransactionData::CreateAccount { account, account_type } => {
+            out.push(5);
+            write_string(out, account);
+            write_account_type(out, account_type);
+        }
+        TransactionData::ChainInfo { chain_id, params, require_signatures } => {
+            out.push(6);
+            write_string(out, chain_id);
+            write_chain_params(out, params);
+            out.push(*require_signatures as u8);
+        }
+        TransactionData::DeployContract { account, code } => {
+            out.push(7);
+            write_string(out, account);
+            write_bytes(out, code);
+        }
+        TransactionData::CallContract { contract, amount, args } => {
+            out.push(8);
+            write_string(out, contract);
+            write_u128(out, *amount);
+            write_u32(out, args.len() as u32);
+            for arg in args {
+                write_string(out, arg);
+            }
+        }
+    }
+}
+
+fn write_account_type(out: &mut Vec<u8>, account_type: &AccountType) {
+    match account_type {
+        AccountType::User => out.push(0),
+        AccountType::Contract => out.push(1),
+        AccountType::Validator { correctly_validated_blocks, incorrectly_validated_blocks, you_get_the_idea } => {
+            out.push(2);
+            write_u128(out, *correctly_validated_blocks);
+            write_u128(out, *incorrectly_validated_blocks);
+            out.push(*you_get_the_idea as u8);
+        }
+    }
+}
+
+fn write_chain_params(out: &mut Vec<u8>, params: &ChainParams) {
+    write_u32(out, params.initial_difficulty);
+    write_u64(out, params.retarget_interval as u64);
+    write_u64(out, params.target_block_time);
+    write_u128(out, params.initial_reward);
+    write_u64(out, params.halving_interval as u64);
+    write_u64(out, params.block_gas_limit);
+    out.push(params.proof_of_authority as u8);
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u128(out: &mut Vec<u8>, value: u128) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, value: &[u8]) {
+    write_u32(out, value.len() as u32);
+    out.extend_from_slice(value);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_bytes(out, value.as_bytes());
+}
+
+fn write_optional_string(out: &mut Vec<u8>, value: &Option<String>) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            write_string(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_optional_hash(out: &mut Vec<u8>, value: &Option<Hash>) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            out.extend_from_slice(value.as_bytes());
+        }
+        None => out.push(0),
+    }
+}
+
+/// Points in time before UNIX epoch are not supported and encoded as UNIX epoch
+fn write_time(out: &mut Vec<u8>, time: &SystemTime) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    write_u64(out, since_epoch.as_secs());
+    write_u32(out, since_epoch.subsec_nanos());
+}
+
+/// Reads values one after another from a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.data.len() < length {
+            return None;
+        }
+
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn u128(&mut self) -> Option<u128> {
+        Some(u128::from_le_bytes(self.bytes(16)?.try_into().ok()?))
+    }
+
+    fn byte_vec(&mut self) -> Option<Vec<u8>> {
+        let length = self.u32()? as usize;
+        Some(self.bytes(length)?.to_vec())
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.byte_vec()?).ok()
+    }
+
+    fn optional_string(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(self.string()?)),
+            _ => None,
+        }
+    }
+
+    fn hash(&mut self) -> Option<Hash> {
+        Some(Hash::from_bytes(self.bytes(HASH_LENGTH)?.try_into().ok()?))
+    }
+
+    fn optional_hash(&mut self) -> Option<Option<Hash>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(self.hash()?)),
+            _ => None,
+        }
+    }
+
+    fn time(&mut self) -> Option<SystemTime> {
+        let seconds = self.u64()?;
+        let nanos = self.u32()?;
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+
+        UNIX_EPOCH.checked_add(Duration::new(seconds, nanos))
+    }
+
+    fn transaction_data(&mut self) -> Option<TransactionData> {
+        let data = match self.u8()? {
+            0 => TransactionData::CreateUserAccount(self.string()?),
+            1 => TransactionData::ChangeStoreValue { key: self.string()?, value: self.string()? },
+            2 => TransactionData::TransferTokens { to: self.string()?, amount: self.u128()? },
+            3 => TransactionData::CreateTokens { receiver: self.string()?, amount: self.u128()? },
+            4 => TransactionData::Coinbase { receiver: self.string()?, amount: self.u128()? },
+            5 => TransactionData::CreateAccount { account: self.string()?, account_type: self.account_type()? },
+            6 => TransactionData::ChainInfo {
+                chain_id: self.string()?,
+                params: self.chain_params()?,
+                require_signatures: match self.u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => return None,
+                },
+            },
+            7 => TransactionData::DeployContract { account: self.string()?, code: self.byte_vec()? },
+            8 => TransactionData::CallContract {
+                contract: self.string()?,
+                amount: self.u128()?,
+                args: (0..self.u32()?).map(|_| self.string()).collect::<Option<_>>()?,
+            },
+            _ => return None,
+        };
+
+        Some(data)
+    }
+
+    fn account_type(&mut self) -> Option<AccountType> {
+        let account_type = match self.u8()? {
+            0 => AccountType::User,
+            1 => AccountType::Contract,
+            2 => AccountType::Validator {
+                correctly_validated_blocks: self.u128()?,
+                incorrectly_validated_blocks: self.u128()?,
+                you_get_the_idea: match self.u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => return None,
+                },
+            },
+            _ => return None,
+        };
+
+        Some(account_type)
+    }
+
+    fn chain_params(&mut self) -> Option<ChainParams> {
+        Some(ChainParams {
+            initial_difficulty: self.u32()?,
+            retarget_interval: self.u64()?.try_into().ok()?,
+            target_block_time: self.u64()?,
+            initial_reward: self.u128()?,
+            halving_interval: self.u64()?.try_into().ok()?,
+            block_gas_limit: self.u64()?,
+            proof_of_authority: match self.u8()? {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+        })
+    }
+
+    /// Reads a transaction without signature
+    fn transaction(&mut self) -> Option<Transaction> {
+        Some(Transaction {
+            nonce: self.u128()?,
+            fee: self.u128()?,
+            gas_limit: self.u64()?,
+            gas_price: self.u128()?,
+            from: self.string()?,
+            created_at: self.time()?,
+            record: self.transaction_data()?,
+            signature: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A transaction which never changes (fixed point in time)
+    fn fixed_transaction() -> Transaction {
+        let mut transaction = Transaction::new(
+            "alice".into(),
+            TransactionData::TransferTokens { to: "bob".into(), amount: 1_000 },
+            7);
+        transaction.created_at = UNIX_EPOCH + Duration::new(1_600_000_000, 5);
+        transaction.set_fee(3);
+        transaction.set_gas_limit(21_000);
+        transaction.set_gas_price(2);
+        transaction
+    }
+
+    fn fixed_block() -> Block {
+        let mut block = Block::new(Some(Hash::digest(b"prev")));
+        block.header.timestamp = 1_600_000_100;
+        block.header.difficulty = 3;
+        block.header.nonce = 42;
+        block.header.validator = Some("validator".into());
+        block.add_transaction(fixed_transaction());
+        let mut chain_info = Transaction::new(String::new(), TransactionData::ChainInfo {
+            chain_id: "test".into(),
+            params: ChainParams::default(),
+            require_signatures: true,
+        }, 0);
+        chain_info.created_at = UNIX_EPOCH;
+        block.add_transaction(chain_info);
+        block
+    }
+
+    #[test]
+    fn transaction_data_vectors() {
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::CreateUserAccount("bob".into()))),
+                   "0003000000626f62");
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::ChangeStoreValue {
+            key: "k".into(), value: "v".into() })),
+                   "01010000006b0100000076");
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::CreateTokens {
+            receiver: "bob".into(), amount: 1 })),
+                   "0303000000626f6201000000000000000000000000000000");
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::Coinbase {
+            receiver: "bob".into(), amount: 2 })),
+                   "0403000000626f6202000000000000000000000000000000");
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::CreateAccount {
+            account: "v".into(), account_type: AccountType::Contract })),
+                   "05010000007601");
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::CreateAccount {
+            account: "v".into(), account_type: AccountType::Validator {
+                correctly_validated_blocks: 3, incorrectly_validated_blocks: 4, you_get_the_idea: true } })),
+                   "05010000007602030000000000000000000000000000000400000000000000000000000000000001");
+        assert_eq!(hex::encode(encode_transaction_data(&TransactionData::CallContract {
+            contract: "c".into(), amount: 1, args: vec!["a".into()] })),
+                   "08010000006301000000000000000000000000000000010000000100000061");
+    }
+
+    #[test]
+    fn transaction_vector() {
+        let transaction = fixed_transaction();
+        assert_eq!(hex::encode(encode_transaction(&transaction)),
+                   "0b070000000000000000000000000000000300000000000000000000000000000008520000000000000200000000000000000000000000000005000000616c69636500105e5f00000000050000000203000000626f62e8030000000000000000000000000000");
+        assert_eq!(transaction.calculate_hash().to_string(),
+                   "0d9a35101306899aa7bd8eafbee3e6f32d6d1c2fdc669f3120f91c7560798fe2");
+    }
+
+    #[test]
+    fn block_header_vector() {
+        let block = fixed_block();
+        assert_eq!(block.calculate_hash().to_string(),
+                   "8a03cc7ceed34ee301b487f0a0b684bfdb606cb922c529d284767baf379c6cb9");
+    }
+
+    #[test]
+    fn block_round_trip() {
+        let mut block = fixed_block();
+        block.update_hash();
+        block.signature = Some("abcd".into());
+
+        let decoded = decode_block(&encode_block(&block)).unwrap();
+        assert_eq!(decoded.calculate_hash(), block.calculate_hash());
+        assert_eq!(decoded.get_signature(), block.get_signature());
+        assert!(decoded.verify_own_hash());
+        assert_eq!(encode_block(&decoded), encode_block(&block));
+    }
+
+    #[test]
+    fn signed_transaction_round_trip() {
+        let mut transaction = fixed_transaction();
+        transaction.signature = Some("abcd".into());
+
+        let decoded = decode_signed_transaction(&encode_signed_transaction(&transaction)).unwrap();
+        assert_eq!(decoded.calculate_hash(), transaction.calculate_hash());
+        assert_eq!(decoded.signature, transaction.signature);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut encoded = encode_block(&fixed_block());
+        encoded[0] = ENCODING_VERSION + 1;
+        assert!(decode_block(&encoded).is_none());
+    }
+}
@@ -0,0 +1,457 @@
+use super::{ContractError, Hash};
+use std::error::Error;
+use std::fmt;
+
+/// Everything that can go wrong while working with the chain
+/// Block and transaction indices are zero based (the genesis block is block 0),
+/// the messages count from 1 like the rest of the chain output does.
+/// Every variant has a numeric code (see `code`) which never changes,
+/// so it can be used to tell errors apart without parsing the message
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainError {
+    /// The stored hash of a block does not match its contents
+    HashMismatch { block: usize },
+
+    /// The stored hash of a block within the chain does not match its contents
+    /// (found by `check_validity`, new blocks are rejected with `HashMismatch`)
+    StoredHashMismatch { block: usize },
+
+    /// A block was mined with another difficulty than the one applying at its height
+    DifficultyMismatch { block: usize, expected: u32, actual: u32 },
+
+    /// The hash of a block does not have enough leading zero bits
+    InsufficientWork { block: usize, difficulty: u32 },
+
+    /// A block is older than the block before it
+    TimestampBeforeParent { block: usize },
+
+    /// A block is too far ahead of the local clock (see `MAX_FUTURE_BLOCK_TIME`)
+    TimestampTooFarAhead { block: usize },
+
+    /// The genesis block points to a previous block
+    GenesisHasParent,
+
+    /// A block other than the genesis block points to nowhere
+    MissingPrevHash { block: usize },
+
+    /// A block does not point to the block before it
+    BrokenLinkage { block: usize, expected: Option<Hash>, actual: Option<Hash> },
+
+    /// A block without any transaction
+    EmptyBlock { block: usize },
+
+    /// A transaction of a block is not signed (but has to be) or the signature is invalid
+    InvalidSignature { block: usize, transaction: usize, account: String },
+
+    /// A transaction of a block could not be executed
+    TransactionFailed { block: usize, transaction: usize, account: String, error: TransactionError },
+
+    /// The genesis block is not the one of the chain spec
+    GenesisMismatch { expected: Hash, actual: Hash },
+
+    /// The chain spec can't be read (the line is zero based)
+    InvalidSpec { line: Option<usize>, message: String },
+
+    /// A second genesis block (without previous hash) was received
+    ConflictingGenesis,
+
+    /// A block waiting for its parent does not match its hash or lacks work
+    InvalidOrphan { block: Hash },
+
+    /// A side branch does not lead back to the main chain (anymore)
+    DetachedBranch { block: Hash },
+
+    /// A side branch forks off too far below the tip of the main chain (or is too long to be kept)
+    StaleBranch { block: Hash },
+
+    /// There is no block at that height
+    UnknownHeight { height: usize },
+
+    /// An account differs from the one replaying all transactions leads to
+    StateMismatch { account: String },
+
+    /// A submitted transaction is not signed (but has to be) or the signature is invalid
+    UnsignedTransaction { account: String },
+
+    /// A submitted transaction is pending already
+    AlreadyPending { transaction: Hash },
+
+    /// A submitted transaction can't be executed on top of the pending ones
+    TransactionRejected { account: String, error: TransactionError },
+
+    /// A transaction is signed with a key which does not belong to the sending account
+    ForeignKey { account: String },
+
+    /// The bytes are no valid secret key
+    InvalidSecretKey,
+
+    /// The text is no valid hash
+    MalformedHash,
+
+    /// Reading or writing the block store failed
+    StoreIo { message: String },
+
+    /// A block within the block store can't be decoded
+    StoreUndecodable { block: usize },
+
+    /// A record in the middle of the block store is damaged
+    StoreCorrupted { block: usize },
+
+    /// A store can only be opened on an empty chain
+    StoreNotEmpty,
+
+    /// A block loaded from the block store is not accepted by the chain
+    StoredBlockRejected { block: usize, error: Box<ChainError> },
+
+    /// The RPC server can't be started
+    RpcIo { message: String },
+
+    /// An exported chain can't be read (see `Blockchain::import_json`)
+    InvalidJson { message: String },
+
+    /// The gas limit of a transaction does not fit into what is left of its block
+    BlockGasExceeded { block: usize, transaction: usize, limit: u64 },
+
+    /// A block is not produced by the validator whose turn it is (see `authority`)
+    WrongValidator { block: usize, expected: String, actual: Option<String> },
+
+    /// A block is not signed by its validator or the signature is invalid
+    InvalidBlockSignature { block: usize, validator: String },
+
+    /// A chain with Proof of Authority has no validator which could produce a block
+    NoValidators { block: usize },
+}
+
+/// Reasons why a single transaction can't be executed against a world state
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionError {
+    /// The account does not exist
+    AccountNotFound { account: String },
+
+    /// The account to create exists already
+    AccountExists { account: String },
+
+    /// The nonce is already used or out of order
+    NonceMismatch { account: String, expected: u128, actual: u128 },
+
+    /// Tokens can only be created by the genesis block
+    TokenCreationNotAllowed,
+
+    /// The receiving account does not exist
+    ReceiverNotFound { account: String },
+
+    /// The account receiving created tokens does not exist
+    TokenReceiverNotFound { account: String },
+
+    /// The account sending tokens does not exist
+    SenderNotFound { account: String },
+
+    /// The sender does not have enough tokens (or the receiver would have too many)
+    Overspent { account: String, amount: u128 },
+
+    /// The transaction is only allowed within the genesis block
+    GenesisOnly,
+
+    /// The sender can't pay the fee
+    FeeNotPayable { account: String, fee: u128 },
+
+    /// A coinbase which is not the first transaction of a block (or not part of a block at all)
+    CoinbaseNotFirst,
+
+    /// A coinbase paying more than the block reward
+    CoinbaseTooLarge { amount: u128, reward: u128 },
+
+    /// A store key is empty or too long
+    InvalidStoreKey { key: String },
+
+    /// A store value is too long
+    StoreValueTooLong { key: String },
+
+    /// The store of the account has no room for another key
+    StoreFull { account: String },
+
+    /// The bytecode of a contract can't be decoded (see `decode_code`)
+    InvalidContractCode { account: String },
+
+    /// The called account is not a contract
+    NotAContract { account: String },
+
+    /// The code of the contract failed (none of its changes are applied)
+    ContractFailed { account: String, error: ContractError },
+
+    /// The gas limit does not cover the intrinsic gas of the record
+    GasLimitTooLow { limit: u64, required: u64 },
+
+    /// The gas limit exceeds the gas limit of a whole block
+    GasLimitTooHigh { limit: u64, maximum: u64 },
+}
+
+impl ChainError {
+    /// Will return the stable numeric code of the error
+    pub fn code(&self) -> u64 {
+        match self {
+            ChainError::HashMismatch { .. } => 93820394,
+            ChainError::StoredHashMismatch { .. } => 665234234,
+            ChainError::DifficultyMismatch { .. } => 2394822,
+            ChainError::InsufficientWork { .. } => 2394820,
+            ChainError::TimestampBeforeParent { .. } => 2394823,
+            ChainError::TimestampTooFarAhead { .. } => 2394824,
+            ChainError::GenesisHasParent => 394823098,
+            ChainError::MissingPrevHash { .. } => 3948231,
+            ChainError::BrokenLinkage { .. } => 3948230,
+            ChainError::EmptyBlock { .. } => 9482930,
+            ChainError::InvalidSignature { .. } => 4398239048,
+            ChainError::TransactionFailed { .. } => 38203984,
+            ChainError::GenesisMismatch { .. } => 394823100,
+            ChainError::InvalidSpec { .. } => 7394823,
+            ChainError::ConflictingGenesis => 394823099,
+            ChainError::InvalidOrphan { .. } => 3948234,
+            ChainError::DetachedBranch { .. } => 3948235,
+            ChainError::StaleBranch { .. } => 3948236,
+            ChainError::UnknownHeight { .. } => 3948232,
+            ChainError::StateMismatch { .. } => 665234235,
+            ChainError::UnsignedTransaction { .. } => 2938471,
+            ChainError::AlreadyPending { .. } => 2938472,
+            ChainError::TransactionRejected { .. } => 2938473,
+            ChainError::ForeignKey { .. } => 2093483,
+            ChainError::InvalidSecretKey => 2093482,
+            ChainError::MalformedHash => 7203948,
+            ChainError::StoreIo { .. } => 5820394,
+            ChainError::StoreUndecodable { .. } => 5820396,
+            ChainError::StoreCorrupted { .. } => 5820397,
+            ChainError::StoreNotEmpty => 5820402,
+            ChainError::StoredBlockRejected { .. } => 5820403,
+            ChainError::RpcIo { .. } => 6620394,
+            ChainError::InvalidJson { .. } => 7203949,
+            ChainError::BlockGasExceeded { .. } => 9482931,
+            ChainError::WrongValidator { .. } => 8820391,
+            ChainError::InvalidBlockSignature { .. } => 8820392,
+            ChainError::NoValidators { .. } => 8820393,
+        }
+    }
+}
+
+impl TransactionError {
+    /// Will return the stable numeric code of the error
+    pub fn code(&self) -> u64 {
+        match self {
+            TransactionError::AccountNotFound { .. } => 93482390,
+            TransactionError::AccountExists { .. } => 934823094,
+            TransactionError::NonceMismatch { .. } => 3948203,
+            TransactionError::TokenCreationNotAllowed => 2394233,
+            TransactionError::ReceiverNotFound { .. } => 3242342380,
+            TransactionError::TokenReceiverNotFound { .. } => 23482309,
+            TransactionError::SenderNotFound { .. } => 23423923,
+            TransactionError::Overspent { .. } => 48239084203,
+            TransactionError::GenesisOnly => 2394236,
+            TransactionError::FeeNotPayable { .. } => 48239084204,
+            TransactionError::CoinbaseNotFirst => 2394234,
+            TransactionError::CoinbaseTooLarge { .. } => 2394235,
+            TransactionError::InvalidStoreKey { .. } => 48728972,
+            TransactionError::StoreValueTooLong { .. } => 48728973,
+            TransactionError::StoreFull { .. } => 48728974,
+            TransactionError::InvalidContractCode { .. } => 5938201,
+            TransactionError::NotAContract { .. } => 5938202,
+            TransactionError::ContractFailed { .. } => 5938203,
+            TransactionError::GasLimitTooLow { .. } => 6938201,
+            TransactionError::GasLimitTooHigh { .. } => 6938202,
+        }
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::HashMismatch { block } =>
+                write!(f, "Stored hash of Block #{} does not match its contents", block + 1)?,
+            ChainError::StoredHashMismatch { block } =>
+                write!(f, "Stored hash for Block #{} does not match calculated hash", block + 1)?,
+            ChainError::DifficultyMismatch { block, expected, actual } =>
+                write!(f, "Block #{} uses difficulty {} but has to use {}", block + 1, actual, expected)?,
+            ChainError::InsufficientWork { block, difficulty } =>
+                write!(f, "Hash of Block #{} does not meet the difficulty of {}", block + 1, difficulty)?,
+            ChainError::TimestampBeforeParent { block } =>
+                write!(f, "Block #{} is older than its previous block", block + 1)?,
+            ChainError::TimestampTooFarAhead { block } =>
+                write!(f, "Block #{} is too far ahead of the current time", block + 1)?,
+            ChainError::GenesisHasParent =>
+                write!(f, "The genesis block has a previous hash set which it shouldn't")?,
+            ChainError::MissingPrevHash { block } =>
+                write!(f, "Block #{} has no previous hash set", block + 1)?,
+            ChainError::BrokenLinkage { block, expected, actual } =>
+                write!(f, "Block #{} is not connected to previous block (Should point to `{}` \
+                but points to `{}`)", block + 1, display_optional(expected), display_optional(actual))?,
+            ChainError::EmptyBlock { block } =>
+                write!(f, "There has to be at least one transaction inside Block #{}", block + 1)?,
+            ChainError::InvalidSignature { block, transaction, account } =>
+                write!(f, "Transaction #{} of Block #{} from `{}` is not signed or has an \
+                invalid signature", transaction + 1, block + 1, account)?,
+            ChainError::TransactionFailed { block, transaction, account, error } =>
+                write!(f, "Transaction #{} of Block #{} from `{}` can not be executed due to `{}`",
+                       transaction + 1, block + 1, account, error)?,
+            ChainError::GenesisMismatch { expected, actual } =>
+                write!(f, "The genesis block is `{}` but has to be `{}`", actual, expected)?,
+            ChainError::InvalidSpec { line: Some(line), message } =>
+                write!(f, "Chain spec is invalid in line {}: {}", line + 1, message)?,
+            ChainError::InvalidSpec { line: None, message } =>
+                write!(f, "Chain spec is invalid: {}", message)?,
+            ChainError::ConflictingGenesis =>
+                write!(f, "The chain has a genesis block already")?,
+            ChainError::InvalidOrphan { block } =>
+                write!(f, "Block `{}` does not match its hash or lacks the work to wait for its parent", block)?,
+            ChainError::DetachedBranch { block } =>
+                write!(f, "The branch of block `{}` is not connected to the main chain", block)?,
+            ChainError::StaleBranch { block } =>
+                write!(f, "The branch of block `{}` forks off too far below the tip of the main chain", block)?,
+            ChainError::UnknownHeight { height } =>
+                write!(f, "There is no Block #{}", height + 1)?,
+            ChainError::StateMismatch { account } =>
+                write!(f, "Account `{}` does not match the replayed transactions", account)?,
+            ChainError::UnsignedTransaction { account } =>
+                write!(f, "Transaction from `{}` is not signed or has an invalid signature", account)?,
+            ChainError::AlreadyPending { transaction } =>
+                write!(f, "Transaction `{}` is already pending", transaction)?,
+            ChainError::TransactionRejected { account, error } =>
+                write!(f, "Transaction from `{}` can not be executed due to `{}`", account, error)?,
+            ChainError::ForeignKey { account } =>
+                write!(f, "The key does not belong to the sending account `{}`", account)?,
+            ChainError::InvalidSecretKey =>
+                write!(f, "Invalid secret key")?,
+            ChainError::MalformedHash =>
+                write!(f, "A hash has to consist of exactly 64 hex characters")?,
+            ChainError::StoreIo { message } =>
+                write!(f, "Block store failed: {}", message)?,
+            ChainError::StoreUndecodable { block } =>
+                write!(f, "Block #{} within the block store can not be decoded", block + 1)?,
+            ChainError::StoreCorrupted { block } =>
+                write!(f, "Record of Block #{} within the block store is corrupted", block + 1)?,
+            ChainError::StoreNotEmpty =>
+                write!(f, "A store can only be opened on an empty chain")?,
+            ChainError::StoredBlockRejected { block, error } =>
+                write!(f, "Stored Block #{} is rejected due to `{}`", block + 1, error)?,
+            ChainError::RpcIo { message } =>
+                write!(f, "RPC server failed: {}", message)?,
+            ChainError::InvalidJson { message } =>
+                write!(f, "The exported chain can not be read: {}", message)?,
+            ChainError::BlockGasExceeded { block, transaction, limit } =>
+                write!(f, "Transaction #{} of Block #{} exceeds the block gas limit of {}",
+                       transaction + 1, block + 1, limit)?,
+            ChainError::WrongValidator { block, expected, actual } =>
+                write!(f, "Block #{} has to be produced by validator `{}` but is produced by {}",
+                       block + 1, expected, actual.as_ref().map_or("nobody".to_string(), |actual| format!("`{}`", actual)))?,
+            ChainError::InvalidBlockSignature { block, validator } =>
+                write!(f, "Block #{} is not signed by validator `{}` or has an invalid signature", block + 1, validator)?,
+            ChainError::NoValidators { block } =>
+                write!(f, "Block #{} can not be produced without any validator", block + 1)?,
+        }
+
+        write!(f, " (Code: {})", self.code())
+    }
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::AccountNotFound { account } =>
+                write!(f, "Account `{}` does not exist", account)?,
+            TransactionError::AccountExists { account } =>
+                write!(f, "User `{}` already exists", account)?,
+            TransactionError::NonceMismatch { account, expected, actual } =>
+                write!(f, "Account `{}` uses nonce {} but should use {}", account, actual, expected)?,
+            TransactionError::TokenCreationNotAllowed =>
+                write!(f, "Token creation is only available on initial creation")?,
+            TransactionError::ReceiverNotFound { account } =>
+                write!(f, "Receiver Account `{}` does not exist", account)?,
+            TransactionError::TokenReceiverNotFound { account } =>
+                write!(f, "Receiver Account `{}` of the created tokens does not exist", account)?,
+            TransactionError::SenderNotFound { account } =>
+                write!(f, "Sending Account `{}` does not exist", account)?,
+            TransactionError::Overspent { account, amount } =>
+                write!(f, "Account `{}` can not send {} tokens (Overspent or Arithmetic error)",
+                       account, amount)?,
+            TransactionError::GenesisOnly =>
+                write!(f, "The transaction is only allowed within the genesis block")?,
+            TransactionError::FeeNotPayable { account, fee } =>
+                write!(f, "Account `{}` can not pay the fee of {} tokens", account, fee)?,
+            TransactionError::CoinbaseNotFirst =>
+                write!(f, "A coinbase has to be the first transaction of a block")?,
+            TransactionError::CoinbaseTooLarge { amount, reward } =>
+                write!(f, "The coinbase pays {} tokens but the reward is {}", amount, reward)?,
+            TransactionError::InvalidStoreKey { key } =>
+                write!(f, "Store key `{}` is empty or too long", key)?,
+            TransactionError::StoreValueTooLong { key } =>
+                write!(f, "Store value of key `{}` is too long", key)?,
+            TransactionError::StoreFull { account } =>
+                write!(f, "Store of account `{}` is full", account)?,
+            TransactionError::InvalidContractCode { account } =>
+                write!(f, "Code of contract `{}` is invalid", account)?,
+            TransactionError::NotAContract { account } =>
+                write!(f, "Account `{}` is not a contract", account)?,
+            TransactionError::ContractFailed { account, error } =>
+                write!(f, "Contract `{}` failed: {}", account, error)?,
+            TransactionError::GasLimitTooLow { limit, required } =>
+                write!(f, "Gas limit of {} is below the required {}", limit, required)?,
+            TransactionError::GasLimitTooHigh { limit, maximum } =>
+                write!(f, "Gas limit of {} exceeds the block gas limit of {}", limit, maximum)?,
+        }
+
+        write!(f, " (Code: {})", self.code())
+    }
+}
+
+impl Error for ChainError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChainError::TransactionFailed { error, .. } => Some(error),
+            ChainError::TransactionRejected { error, .. } => Some(error),
+            ChainError::StoredBlockRejected { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Error for TransactionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransactionError::ContractFailed { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+fn display_optional(hash: &Option<Hash>) -> String {
+    match hash {
+        Some(hash) => hash.to_string(),
+        None => "nothing".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_contains_code_and_context() {
+        let error = ChainError::TransactionFailed {
+            block: 2,
+            transaction: 0,
+            account: "alice".into(),
+            error: TransactionError::Overspent { account: "alice".into(), amount: 5 },
+        };
+
+        assert_eq!(error.code(), 38203984);
+        assert_eq!(error.to_string(), "Transaction #1 of Block #3 from `alice` can not be executed \
+        due to `Account `alice` can not send 5 tokens (Overspent or Arithmetic error) \
+        (Code: 48239084203)` (Code: 38203984)");
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn codes_of_the_string_errors_are_kept() {
+        assert_eq!(ChainError::StoredHashMismatch { block: 0 }.code(), 665234234);
+        assert_eq!(TransactionError::TokenReceiverNotFound { account: "bob".into() }.code(), 23482309);
+        assert_eq!(TransactionError::SenderNotFound { account: "bob".into() }.code(), 23423923);
+        assert_eq!(TransactionError::ReceiverNotFound { account: "bob".into() }.code(), 3242342380);
+        assert_eq!(TransactionError::AccountNotFound { account: "bob".into() }.code(), 93482390);
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Account, Block, Blockchain, ChainError, ChainParams, ChainSpec, Hash, Transaction};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+
+/// A chain as it is serialized (see `Blockchain::export_json`)
+/// Nothing of it is trusted: the chain is rebuilt by `replay`
+#[derive(serde::Deserialize)]
+struct ChainData {
+    blocks: Vec<Block>,
+    accounts: HashMap<String, Account>,
+    pending_transactions: Vec<Transaction>,
+    require_signatures: bool,
+    params: ChainParams,
+    genesis_hash: Option<Hash>,
+}
+
+impl ChainData {
+    fn parse(json: &str) -> Result<Self, ChainError> {
+        serde_json::from_str(json).map_err(|err| ChainError::InvalidJson { message: err.to_string() })
+    }
+
+    /// Replays every block through `append_block` of the given (empty) chain, so every rule
+    /// of the chain is checked again, and resubmits the pending transactions
+    /// The settings and the accounts within the data have to match the replayed ones
+    fn replay(self, mut bc: Blockchain) -> Result<Blockchain, ChainError> {
+        if self.params != *bc.get_params() || self.require_signatures != bc.requires_signatures()
+            || self.genesis_hash != bc.get_genesis_hash() {
+            return Err(ChainError::InvalidJson { message: "The chain does not follow the given spec".into() });
+        }
+
+        for block in self.blocks {
+            bc.append_block(block)?;
+        }
+
+        let accounts = self.accounts;
+        let mut ids: Vec<&String> = accounts.keys().chain(bc.accounts.keys()).collect();
+        ids.sort();
+        if let Some(id) = ids.into_iter().find(|id| accounts.get(*id) != bc.accounts.get(*id)) {
+            return Err(ChainError::StateMismatch { account: id.clone() });
+        }
+
+        for transaction in self.pending_transactions {
+            bc.submit_transaction(transaction)?;
+        }
+
+        Ok(bc)
+    }
+}
+
+/// Serializes a map ordered by its keys, so the same chain always leads to the same JSON
+pub(super) fn sorted<V: Serialize, S: Serializer>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl Blockchain {
+    /// Will serialize the chain (blocks, accounts, pending transactions and settings) as JSON
+    /// Side branches and the block store are not part of it
+    pub fn export_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("A chain can always be serialized")
+    }
+
+    /// Will read a chain written by `export_json`
+    /// The chain is rebuilt block by block under the rules within the file, so a tampered file
+    /// is rejected (but anyone can write a valid chain, see `import_json_with_spec`)
+    pub fn import_json(json: &str) -> Result<Self, ChainError> {
+        let data = ChainData::parse(json)?;
+
+        let mut bc = Blockchain::new();
+        bc.set_params(data.params.clone());
+        bc.set_require_signatures(data.require_signatures);
+        bc.genesis_hash = data.genesis_hash;
+        data.replay(bc)
+    }
+
+    /// Like `import_json`, but the chain has to be a chain of the given spec
+    /// The rules come from the spec (not from the file)
+    pub fn import_json_with_spec(json: &str, spec: &ChainSpec) -> Result<Self, ChainError> {
+        ChainData::parse(json)?.replay(Blockchain::with_spec(spec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{TransactionData, WorldState};
+    use super::*;
+
+    fn spec() -> ChainSpec {
+        ChainSpec::parse("chain_id = \"json\"\ninitial_reward = 10\n\
+                          [accounts.alice]\nbalance = 100\n[accounts.bob]\n").unwrap()
+    }
+
+    fn chain() -> Blockchain {
+        let mut bc = Blockchain::from_spec(&spec()).unwrap();
+
+        bc.submit_transaction(Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 30 }, 0)).unwrap();
+        let mut block = bc.build_block_template_for("bob".into());
+        block.mine(bc.get_difficulty());
+        bc.append_block(block).unwrap();
+
+        bc.submit_transaction(Transaction::new("bob".into(), TransactionData::ChangeStoreValue {
+            key: "color".into(), value: "blue".into() }, 0)).unwrap();
+        bc
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let bc = chain();
+        let imported = Blockchain::import_json_with_spec(&bc.export_json(), &spec()).unwrap();
+
+        assert_eq!(imported.blocks.len(), 2);
+        assert_eq!(imported.get_last_block_hash(), bc.get_last_block_hash());
+        assert_eq!(imported.accounts, bc.accounts);
+        assert_eq!(imported.get_pending_transactions().len(), 1);
+        assert_eq!(imported.get_genesis_hash(), bc.get_genesis_hash());
+        assert_eq!(imported.export_json(), bc.export_json());
+    }
+
+    #[test]
+    fn round_trip_without_spec() {
+        let mut bc = Blockchain::new();
+        bc.set_params(ChainParams { initial_difficulty: 1, initial_reward: 5, ..ChainParams::default() });
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new("alice".into(),
+                                                 TransactionData::CreateUserAccount("alice".into()), 0));
+        genesis.mine(bc.get_difficulty());
+        bc.append_block(genesis).unwrap();
+        let mut block = bc.build_block_template_for("alice".into());
+        block.mine(bc.get_difficulty());
+        bc.append_block(block).unwrap();
+
+        // The rules of the file apply
+        let imported = Blockchain::import_json(&bc.export_json()).unwrap();
+        assert_eq!(imported.get_params(), bc.get_params());
+        assert_eq!(imported.get_genesis_hash(), None);
+        assert_eq!(imported.get_balance("alice"), Some(5));
+        assert_eq!(imported.export_json(), bc.export_json());
+
+        // But the chain is rebuilt all the same
+        let mut tampered = bc.clone();
+        tampered.accounts.get_mut("alice").unwrap().tokens = 500;
+        assert_eq!(Blockchain::import_json(&tampered.export_json()).err(),
+                   Some(ChainError::StateMismatch { account: "alice".into() }));
+        assert!(Blockchain::import_json_with_spec(&bc.export_json(), &spec()).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_exports() {
+        let bc = chain();
+
+        // More tokens within a transaction break the hash of its block
+        let mut tampered = bc.clone();
+        if let TransactionData::TransferTokens { amount, .. } = &mut tampered.blocks[1].transactions[1].record {
+            *amount = 90;
+        }
+        assert_eq!(Blockchain::import_json_with_spec(&tampered.export_json(), &spec()).map_err(|err| err.code()).err(),
+                   Some(93820394));
+
+        // Edited accounts don't match the replayed ones
+        let mut tampered = bc.clone();
+        tampered.accounts.insert("bob".into(), bc.accounts["alice"].clone());
+        assert_eq!(Blockchain::import_json_with_spec(&tampered.export_json(), &spec()).map_err(|err| err.code()).err(),
+                   Some(665234235));
+
+        assert_eq!(Blockchain::import_json_with_spec("{}", &spec()).map_err(|err| err.code()).err(), Some(7203949));
+
+        // The file can't bring its own rules (e.g. without signatures)
+        let mut other_rules = bc.clone();
+        other_rules.set_require_signatures(true);
+        let mut signed = spec();
+        signed.require_signatures = true;
+        assert_eq!(Blockchain::import_json_with_spec(&bc.export_json(), &signed).map_err(|err| err.code()).err(),
+                   Some(7203949));
+        assert_eq!(Blockchain::import_json_with_spec(&other_rules.export_json(), &spec()).map_err(|err| err.code()).err(),
+                   Some(7203949));
+
+        // Neither can it start another chain
+        let mut other = spec();
+        other.chain_id = "other".into();
+        let forged = Blockchain::from_spec(&other).unwrap();
+        assert_eq!(Blockchain::import_json_with_spec(&forged.export_json(), &spec()).map_err(|err| err.code()).err(),
+                   Some(7203949));
+    }
+}
@@ -0,0 +1,452 @@
+use super::{Blockchain, Block, BlockHeader, ChainError, Hash};
+
+/// Maximum amount of blocks waiting for their parent (the oldest ones make room for new ones)
+pub const MAX_ORPHANS: usize = 64;
+
+/// Maximum amount of blocks a side branch may fork off below the tip of the main chain
+/// (deeper branches are dropped as the main chain grows)
+pub const MAX_FORK_DEPTH: usize = 64;
+
+/// Maximum amount of side blocks (the branches forking off lowest make room for new ones)
+pub const MAX_SIDE_BLOCKS: usize = 256;
+
+/// Where a block handed to `Blockchain::receive_block` ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// The block is part of the main chain
+    MainChain,
+
+    /// The block is part of a side branch (which has less work than the main chain)
+    SideBranch,
+
+    /// The parent of the block is not known yet
+    Orphan,
+
+    /// The block is known already
+    Duplicate,
+}
+
+/// The result of `Blockchain::receive_block`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceiveOutcome {
+    pub status: BlockStatus,
+
+    /// Blocks removed from the main chain by a reorganization (oldest first)
+    /// They are kept as side branch
+    pub disconnected: Vec<Hash>,
+
+    /// Blocks which replaced them (oldest first)
+    pub connected: Vec<Hash>,
+}
+
+/// The work needed to mine a block of the given difficulty (2^difficulty hashes on average)
+pub fn block_work(difficulty: u32) -> u128 {
+    1u128.checked_shl(difficulty).unwrap_or(u128::MAX)
+}
+
+impl Blockchain {
+    /// Will add a block to the block tree. Other than `append_block` it also accepts blocks
+    /// which don't extend the main chain: They are kept as side branches (or as orphans,
+    /// if their parent is unknown yet). As soon as a side branch has more cumulative work
+    /// than the main chain, the chain is reorganized onto it
+    /// Side branches are only executed when they become the main chain, and they are
+    /// not persisted to the block store (nor kept forever, see `MAX_FORK_DEPTH` and `MAX_SIDE_BLOCKS`)
+    pub fn receive_block(&mut self, block: Block) -> Result<ReceiveOutcome, ChainError> {
+        let hash = block.calculate_hash();
+        let mut outcome = ReceiveOutcome {
+            status: BlockStatus::Duplicate,
+            disconnected: Vec::new(),
+            connected: Vec::new(),
+        };
+
+        if self.is_known(&hash) {
+            return Ok(outcome);
+        }
+
+        // Blocks whose parent just became known are placed right after it
+        let mut waiting = vec![block];
+        while let Some(block) = waiting.pop() {
+            let placed = block.calculate_hash();
+            match self.place_block(block, &mut outcome) {
+                Ok(()) => {}
+                // Orphans failing later on don't affect the received block
+                Err(err) if placed == hash => return Err(err),
+                Err(_) => continue,
+            }
+
+            let (children, orphans) = std::mem::take(&mut self.orphans).into_iter()
+                .partition(|orphan| orphan.get_prev_hash() == Some(placed));
+            self.orphans = orphans;
+            waiting.extend(children);
+        }
+
+        // The main chain may have grown past some side branches
+        self.prune_side_branches(MAX_SIDE_BLOCKS, None);
+
+        outcome.status = if self.get_height(&hash).is_some() {
+            BlockStatus::MainChain
+        } else if self.side_blocks.contains_key(&hash) {
+            BlockStatus::SideBranch
+        } else {
+            BlockStatus::Orphan
+        };
+
+        Ok(outcome)
+    }
+
+    /// Will return the height of the main chain block with the given hash (if any)
+    /// Compares the stored block hashes (checked by `append_block`), so nothing is rehashed
+    pub fn get_height(&self, hash: &Hash) -> Option<usize> {
+        self.blocks.iter().rposition(|block| block.hash == Some(*hash))
+    }
+
+    /// Will return all blocks of the side branches
+    pub fn get_side_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.side_blocks.values()
+    }
+
+    /// Will return all blocks waiting for their parent (oldest first)
+    pub fn get_orphans(&self) -> impl Iterator<Item = &Block> {
+        self.orphans.iter()
+    }
+
+    /// Will return the sum of the work of all main chain blocks
+    pub fn get_total_work(&self) -> u128 {
+        self.blocks.iter()
+            .fold(0u128, |work, block| work.saturating_add(block_work(block.get_difficulty())))
+    }
+
+    fn is_known(&self, hash: &Hash) -> bool {
+        self.side_blocks.contains_key(hash) || self.get_height(hash).is_some()
+            || self.orphans.iter().any(|orphan| orphan.hash == Some(*hash))
+    }
+
+    /// Will put a single block into the tree and reorganize if needed
+    fn place_block(&mut self, block: Block, outcome: &mut ReceiveOutcome) -> Result<(), ChainError> {
+        // Extending the main chain (or starting it) is the common case
+        if block.get_prev_hash() == self.get_last_block_hash() {
+            let hash = block.calculate_hash();
+            self.append_block(block)?;
+            if !outcome.disconnected.is_empty() {
+                outcome.connected.push(hash);
+            }
+            return Ok(());
+        }
+
+        let prev_hash = match block.get_prev_hash() {
+            Some(prev_hash) => prev_hash,
+            None => return Err(ChainError::ConflictingGenesis),
+        };
+
+        // Without the parent the block can't be checked completely. So it has to match its hash
+        // and carry at least the work the main chain needs right now, which makes junk expensive
+        if !self.side_blocks.contains_key(&prev_hash) && self.get_height(&prev_hash).is_none() {
+            if !block.verify_own_hash() || block.get_difficulty() < self.get_difficulty()
+                || !block.meets_difficulty(block.get_difficulty()) {
+                return Err(ChainError::InvalidOrphan { block: block.calculate_hash() });
+            }
+
+            while self.orphans.len() >= MAX_ORPHANS {
+                self.orphans.pop_front();
+            }
+            self.orphans.push_back(block);
+            return Ok(());
+        }
+
+        // Check the header against the branch it belongs to (difficulty, work, time)
+        let (fork_height, branch) = self.branch_to(prev_hash)?;
+        if fork_height + MAX_FORK_DEPTH < self.len() - 1 {
+            return Err(ChainError::StaleBranch { block: block.calculate_hash() });
+        }
+
+        let mut headers: Vec<&BlockHeader> = self.blocks[..=fork_height].iter()
+            .map(|block| block.get_header())
+            .collect();
+        headers.extend(branch.iter().map(|hash| self.side_blocks[hash].get_header()));
+        headers.push(block.get_header());
+
+        let height = headers.len() - 1;
+        if !block.verify_own_hash() {
+            return Err(ChainError::HashMismatch { block: height });
+        }
+
+        self.params.check_header(&headers, height)?;
+
+        if block.get_transaction_count() == 0 {
+            return Err(ChainError::EmptyBlock { block: height });
+        }
+
+        let branch_work = headers.iter()
+            .fold(0u128, |work, header| work.saturating_add(block_work(header.get_difficulty())));
+        let hash = block.calculate_hash();
+        self.prune_side_branches(MAX_SIDE_BLOCKS - 1, Some(*branch.first().unwrap_or(&hash)));
+        if self.side_blocks.len() >= MAX_SIDE_BLOCKS {
+            return Err(ChainError::StaleBranch { block: hash });
+        }
+        self.side_blocks.insert(hash, block);
+
+        if branch_work > self.get_total_work() {
+            self.reorganize(hash, outcome)?;
+        }
+
+        Ok(())
+    }
+
+    /// Will walk back from a side block until the main chain is reached
+    /// Returns the height of the main chain block the branch starts at and the hashes of the
+    /// side blocks (oldest first). A main chain block results in an empty branch
+    fn branch_to(&self, mut hash: Hash) -> Result<(usize, Vec<Hash>), ChainError> {
+        let mut branch = Vec::new();
+        while let Some(block) = self.side_blocks.get(&hash) {
+            branch.push(hash);
+            hash = block.get_prev_hash().ok_or(ChainError::DetachedBranch { block: hash })?;
+        }
+
+        branch.reverse();
+        match self.get_height(&hash) {
+            Some(height) => Ok((height, branch)),
+            None => Err(ChainError::DetachedBranch { block: hash }),
+        }
+    }
+
+    /// Will make the branch ending with `tip` the main chain
+    /// If a block of the branch turns out to be invalid, the branch is dropped from that block on
+    /// and the previous main chain is restored
+    fn reorganize(&mut self, tip: Hash, outcome: &mut ReceiveOutcome) -> Result<(), ChainError> {
+        let (fork_height, branch) = self.branch_to(tip)?;
+        let disconnected = self.rewind(fork_height)?;
+
+        for hash in branch.iter() {
+            let block = self.side_blocks[hash].clone();
+            if let Err(err) = self.append_block(block) {
+                // Forget the invalid block and everything built on top of it
+                self.drop_side_descendants(hash);
+
+                // Bring back the old main chain (it was valid before, so it still is)
+                self.rewind(fork_height)?;
+                for block in disconnected {
+                    self.append_block(block)?;
+                }
+
+                return Err(err);
+            }
+        }
+
+        // The old blocks are now a side branch
+        for block in disconnected {
+            let hash = block.calculate_hash();
+            outcome.connected.retain(|connected| *connected != hash);
+            outcome.disconnected.push(hash);
+            self.side_blocks.insert(hash, block);
+        }
+
+        for hash in branch {
+            self.side_blocks.remove(&hash);
+            if let Some(position) = outcome.disconnected.iter().position(|disconnected| *disconnected == hash) {
+                // Going back to a branch reported as disconnected in the same call
+                outcome.disconnected.remove(position);
+            } else {
+                outcome.connected.push(hash);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Will drop the side branches forking off more than `MAX_FORK_DEPTH` blocks below the tip
+    /// and, while there are more than `limit` side blocks, the branches forking off lowest
+    /// (the branch starting with the side block `keep` stays)
+    fn prune_side_branches(&mut self, limit: usize, keep: Option<Hash>) {
+        let tip = self.len().saturating_sub(1);
+        let mut roots: Vec<(usize, Hash)> = self.side_blocks.iter()
+            .filter_map(|(hash, block)| Some((self.get_height(&block.get_prev_hash()?)?, *hash)))
+            .collect();
+        roots.sort();
+
+        for (fork_height, root) in roots {
+            let too_deep = fork_height + MAX_FORK_DEPTH < tip;
+            if too_deep || (self.side_blocks.len() > limit && Some(root) != keep) {
+                self.drop_side_descendants(&root);
+            }
+        }
+    }
+
+    /// Will remove a side block and all side blocks (and orphans) built on top of it
+    pub(super) fn drop_side_descendants(&mut self, hash: &Hash) {
+        let mut dropped = vec![*hash];
+        while let Some(hash) = dropped.pop() {
+            self.side_blocks.remove(&hash);
+            self.orphans.retain(|orphan| orphan.get_prev_hash() != Some(hash));
+            dropped.extend(self.side_blocks.iter()
+                .filter(|(_, block)| block.get_prev_hash() == Some(hash))
+                .map(|(child, _)| *child));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Transaction, TransactionData, WorldState};
+    use super::*;
+
+    fn genesis() -> Block {
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new("alice".into(),
+                                                 TransactionData::CreateUserAccount("alice".into()), 0));
+        genesis.mine(0);
+        genesis
+    }
+
+    /// A block on top of `prev_hash` which changes alice's store
+    fn child(prev_hash: Hash, nonce: u128, value: &str, difficulty: u32) -> Block {
+        let mut block = Block::new(Some(prev_hash));
+        block.add_transaction(Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+            key: "k".into(), value: value.into() }, nonce));
+        block.mine(difficulty);
+        block
+    }
+
+    #[test]
+    fn heavier_branch_wins() {
+        let mut bc = Blockchain::new();
+        let genesis = genesis();
+        let genesis_hash = genesis.calculate_hash();
+        assert_eq!(bc.receive_block(genesis).unwrap().status, BlockStatus::MainChain);
+
+        let a1 = child(genesis_hash, 0, "a1", 0);
+        let a1_hash = a1.calculate_hash();
+        bc.receive_block(a1).unwrap();
+
+        // A competing block with the same work is kept on the side
+        let b1 = child(genesis_hash, 0, "b1", 0);
+        let b1_hash = b1.calculate_hash();
+        let b2 = child(b1_hash, 1, "b2", 0);
+        let b2_hash = b2.calculate_hash();
+
+        // The child arrives first and has to wait for its parent
+        assert_eq!(bc.receive_block(b2.clone()).unwrap().status, BlockStatus::Orphan);
+        assert_eq!(bc.receive_block(b2).unwrap().status, BlockStatus::Duplicate);
+
+        // Once the parent arrives, the branch has more work and takes over
+        let outcome = bc.receive_block(b1).unwrap();
+        assert_eq!(outcome.status, BlockStatus::MainChain);
+        assert_eq!(outcome.disconnected, vec![a1_hash]);
+        assert_eq!(outcome.connected, vec![b1_hash, b2_hash]);
+        assert_eq!(bc.len(), 3);
+        assert_eq!(bc.get_store_value("alice", "k"), Some(&"b2".to_string()));
+        assert_eq!(bc.get_side_blocks().count(), 1);
+        assert_eq!(bc.get_orphans().count(), 0);
+    }
+
+    #[test]
+    fn invalid_branch_is_dropped() {
+        let mut bc = Blockchain::new();
+        let genesis = genesis();
+        let genesis_hash = genesis.calculate_hash();
+        bc.receive_block(genesis).unwrap();
+        bc.receive_block(child(genesis_hash, 0, "a1", 0)).unwrap();
+
+        // The second block of the branch replays nonce 0
+        let b1 = child(genesis_hash, 0, "b1", 0);
+        let b2 = child(b1.calculate_hash(), 0, "b2", 0);
+        assert_eq!(bc.receive_block(b1).unwrap().status, BlockStatus::SideBranch);
+        assert!(bc.receive_block(b2).is_err());
+
+        assert_eq!(bc.len(), 2);
+        assert_eq!(bc.get_store_value("alice", "k"), Some(&"a1".to_string()));
+        assert_eq!(bc.get_side_blocks().count(), 1);
+        assert!(bc.check_full_validity().is_ok());
+    }
+
+    #[test]
+    fn rewinding_drops_branches_on_removed_blocks() {
+        let mut bc = Blockchain::new();
+        let genesis = genesis();
+        let mut prev_hash = genesis.calculate_hash();
+        bc.receive_block(genesis).unwrap();
+
+        let mut main = Vec::new();
+        for nonce in 0..3 {
+            let block = child(prev_hash, nonce, "a", 0);
+            prev_hash = block.calculate_hash();
+            main.push(prev_hash);
+            bc.receive_block(block).unwrap();
+        }
+
+        let b2 = child(main[0], 1, "b2", 0);
+        let b3 = child(b2.calculate_hash(), 2, "b3", 0);
+        assert_eq!(bc.receive_block(b2).unwrap().status, BlockStatus::SideBranch);
+
+        // B2 was built on A1, which is gone now, so B3 has to wait for a parent again
+        bc.rewind_to(0).unwrap();
+        assert_eq!(bc.get_side_blocks().count(), 0);
+        assert_eq!(bc.receive_block(b3).unwrap().status, BlockStatus::Orphan);
+        assert_eq!(bc.get_height(&main[0]), None);
+        assert_eq!(bc.len(), 1);
+    }
+
+    #[test]
+    fn orphans_need_work_and_make_room() {
+        let mut bc = Blockchain::new();
+        bc.set_difficulty(2);
+        let mut genesis = genesis();
+        genesis.mine(2);
+        bc.receive_block(genesis).unwrap();
+
+        // Junk without the work (or with a wrong hash) is not kept
+        let unknown = Block::new(None).calculate_hash();
+        let junk = child(unknown, 0, "junk", 0);
+        assert_eq!(bc.receive_block(junk.clone()).err(),
+                   Some(ChainError::InvalidOrphan { block: junk.calculate_hash() }));
+        let mut tampered = child(unknown, 0, "tampered", 2);
+        tampered.header.nonce += 1;
+        assert_eq!(bc.receive_block(tampered).err().map(|err| err.code()), Some(3948234));
+
+        // Once the room is used up, the oldest orphans are dropped
+        let first = child(unknown, 0, "0", 2);
+        bc.receive_block(first.clone()).unwrap();
+        for i in 1..=MAX_ORPHANS {
+            let orphan = child(unknown, 0, &i.to_string(), 2);
+            assert_eq!(bc.receive_block(orphan).unwrap().status, BlockStatus::Orphan);
+        }
+        assert_eq!(bc.get_orphans().count(), MAX_ORPHANS);
+        assert!(bc.get_orphans().all(|orphan| orphan.calculate_hash() != first.calculate_hash()));
+    }
+
+    #[test]
+    fn side_branches_are_limited() {
+        let mut bc = Blockchain::new();
+        let genesis = genesis();
+        let genesis_hash = genesis.calculate_hash();
+        bc.receive_block(genesis).unwrap();
+        let a1 = child(genesis_hash, 0, "a1", 0);
+        let a1_hash = a1.calculate_hash();
+        bc.receive_block(a1).unwrap();
+        bc.receive_block(child(a1_hash, 1, "a2", 0)).unwrap();
+
+        // Once the room is used up, the branches forking off lowest are dropped
+        let low = child(genesis_hash, 0, "low", 0);
+        assert_eq!(bc.receive_block(low.clone()).unwrap().status, BlockStatus::SideBranch);
+        for i in 1..=MAX_SIDE_BLOCKS {
+            let side = child(a1_hash, 1, &i.to_string(), 0);
+            assert_eq!(bc.receive_block(side).unwrap().status, BlockStatus::SideBranch);
+        }
+        assert_eq!(bc.get_side_blocks().count(), MAX_SIDE_BLOCKS);
+        assert!(bc.get_side_blocks().all(|side| side.calculate_hash() != low.calculate_hash()));
+
+        // Branches falling too far behind the growing main chain are dropped as well
+        let mut prev_hash = bc.get_last_block_hash().unwrap();
+        for nonce in 2..(MAX_FORK_DEPTH as u128 + 2) {
+            let block = child(prev_hash, nonce, "a", 0);
+            prev_hash = block.calculate_hash();
+            bc.receive_block(block).unwrap();
+        }
+        assert_eq!(bc.len(), MAX_FORK_DEPTH + 3);
+        assert_eq!(bc.get_side_blocks().count(), 0);
+
+        let stale = child(a1_hash, 1, "stale", 0);
+        assert_eq!(bc.receive_block(stale.clone()).err(),
+                   Some(ChainError::StaleBranch { block: stale.calculate_hash() }));
+        assert!(bc.check_full_validity().is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+//! Gas: every transaction pays for the work it causes
+//!
+//! Every transaction names a gas limit (the most work it may cause) and a gas price (tokens per
+//! unit of gas). Before it runs, the sender pays `gas_limit * gas_price` (on top of the fee).
+//! Afterwards the unused gas is refunded and the used gas goes to the producer of the block.
+//!
+//! The gas a transaction uses is the intrinsic gas of its record (see
+//! `TransactionData::intrinsic_gas`) plus, for contract calls, the gas of every instruction
+//! executed (see `Instruction::gas_cost`). A call running out of gas fails like any other
+//! failing call: the transaction stays within the block, the sender pays for all the gas used
+//! and nothing else of the call is applied (see `Receipt`).
+//!
+//! The gas limits of the transactions within a block may not add up to more than the
+//! `block_gas_limit` of the chain (counting the gas actually used by the transactions before).
+//! Only the genesis block is not limited.
+
+use super::{Instruction, TransactionData, TransactionError};
+
+/// Gas limit of a new transaction (enough for everything but larger contracts)
+pub const DEFAULT_GAS_LIMIT: u64 = 100_000;
+
+/// Default for `ChainParams::block_gas_limit`
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 10_000_000;
+
+/// Paid by every transaction (except the coinbase)
+pub const TRANSACTION_GAS: u64 = 1_000;
+
+/// Creating an account (or deploying a contract)
+pub const ACCOUNT_GAS: u64 = 10_000;
+
+/// Moving or creating tokens
+pub const TRANSFER_GAS: u64 = 1_000;
+
+/// Writing a single value into a store
+pub const STORE_GAS: u64 = 2_000;
+
+/// Reading a single value of a store
+pub const LOAD_GAS: u64 = 100;
+
+/// Starting a contract call
+pub const CALL_GAS: u64 = 2_000;
+
+/// Every byte of a store value, bytecode or call argument
+pub const BYTE_GAS: u64 = 10;
+
+/// The result of a transaction which is part of a block
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Receipt {
+    /// The gas the transaction used (the sender paid `gas_used * gas_price` for it)
+    pub gas_used: u64,
+
+    /// Set if the contract call of the transaction failed
+    /// (the transaction is still part of the block, but only its gas and fee were paid)
+    pub error: Option<TransactionError>,
+}
+
+impl TransactionData {
+    /// Will return the gas a transaction with this record uses at least (before running any code)
+    pub fn intrinsic_gas(&self) -> u64 {
+        let bytes = |length: usize| BYTE_GAS.saturating_mul(length as u64);
+
+        match self {
+            TransactionData::Coinbase { .. } => 0,
+            TransactionData::ChainInfo { .. } => TRANSACTION_GAS,
+            TransactionData::CreateUserAccount(_) | TransactionData::CreateAccount { .. } =>
+                TRANSACTION_GAS + ACCOUNT_GAS,
+            TransactionData::TransferTokens { .. } | TransactionData::CreateTokens { .. } =>
+                TRANSACTION_GAS + TRANSFER_GAS,
+            TransactionData::ChangeStoreValue { key, value } =>
+                (TRANSACTION_GAS + STORE_GAS).saturating_add(bytes(key.len() + value.len())),
+            TransactionData::DeployContract { code, .. } =>
+                (TRANSACTION_GAS + ACCOUNT_GAS).saturating_add(bytes(code.len())),
+            TransactionData::CallContract { args, .. } =>
+                (TRANSACTION_GAS + CALL_GAS).saturating_add(bytes(args.iter().map(String::len).sum())),
+        }
+    }
+}
+
+impl Instruction {
+    /// Will return the gas it takes to execute the instruction once
+    pub fn gas_cost(&self) -> u64 {
+        match self {
+            Instruction::Stop | Instruction::Revert => 0,
+            Instruction::Push(_) | Instruction::Pop | Instruction::Dup(_) | Instruction::Swap
+            | Instruction::Arg(_) | Instruction::ArgCount | Instruction::Caller | Instruction::CallValue
+            | Instruction::Balance | Instruction::Address => 2,
+            Instruction::Add | Instruction::Sub | Instruction::Mul | Instruction::Div | Instruction::Mod
+            | Instruction::Eq | Instruction::Lt | Instruction::Gt | Instruction::Not => 3,
+            Instruction::Jump(_) | Instruction::JumpIf(_) => 5,
+            Instruction::Concat => 10,
+            Instruction::Load => LOAD_GAS,
+            Instruction::Store => STORE_GAS,
+            Instruction::Transfer => TRANSFER_GAS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{encode_code, Block, Blockchain, ChainError, ChainSpec, ContractError, Transaction, WorldState};
+    use super::*;
+
+    fn chain(block_gas_limit: u64) -> Blockchain {
+        let spec = ChainSpec::parse(&format!("chain_id = \"gas\"\nblock_gas_limit = {}\n\
+                                              [accounts.alice]\nbalance = 100000\n[accounts.bob]\n",
+                                             block_gas_limit)).unwrap();
+        Blockchain::from_spec(&spec).unwrap()
+    }
+
+    fn transfer(nonce: u128, gas_limit: u64, gas_price: u128) -> Transaction {
+        let mut transaction = Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 10 }, nonce);
+        transaction.set_gas_limit(gas_limit);
+        transaction.set_gas_price(gas_price);
+        transaction
+    }
+
+    #[test]
+    fn used_gas_is_charged_and_the_rest_refunded() {
+        let mut bc = chain(DEFAULT_BLOCK_GAS_LIMIT);
+        bc.submit_transaction(transfer(0, 5_000, 1)).unwrap();
+
+        // Code running forever is stopped by its gas limit
+        bc.submit_transaction(Transaction::new("alice".into(), TransactionData::DeployContract {
+            account: "loop".into(), code: encode_code(&[Instruction::Jump(0)]) }, 1)).unwrap();
+        let mut call = Transaction::new("alice".into(), TransactionData::CallContract {
+            contract: "loop".into(), amount: 0, args: Vec::new() }, 2);
+        call.set_gas_limit(20_000);
+        call.set_gas_price(1);
+
+        let mut block = bc.build_block_template_for("bob".into());
+        block.mine(0);
+        bc.append_block(block).unwrap();
+
+        // The mempool doesn't take a call which is going to fail, a block does
+        assert_eq!(bc.submit_transaction(call.clone()).map_err(|err| err.code()), Err(2938473));
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(Transaction::coinbase("bob".into(), 0));
+        block.add_transaction(call);
+        block.mine(0);
+        bc.append_block(block).unwrap();
+
+        assert_eq!(bc.get_receipts(1).unwrap()[1], Receipt { gas_used: TRANSACTION_GAS + TRANSFER_GAS, error: None });
+        assert_eq!(bc.get_receipts(2).unwrap()[1], Receipt {
+            gas_used: 20_000,
+            error: Some(TransactionError::ContractFailed {
+                account: "loop".into(), error: ContractError::OutOfGas { position: 0 } }),
+        });
+        assert_eq!(bc.get_balance("alice"), Some(100_000 - 10 - 2_000 - 20_000));
+        assert_eq!(bc.get_balance("bob"), Some(10 + 2_000 + 20_000));
+        assert!(bc.check_full_validity().is_ok());
+    }
+
+    #[test]
+    fn gas_limits_are_enforced() {
+        let mut bc = chain(5_000);
+
+        let rejected = |bc: &mut Blockchain, transaction: Transaction| match bc.submit_transaction(transaction) {
+            Err(ChainError::TransactionRejected { error, .. }) => error,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(rejected(&mut bc, transfer(0, 1_999, 0)), TransactionError::GasLimitTooLow { limit: 1_999, required: 2_000 });
+        assert_eq!(rejected(&mut bc, transfer(0, 5_001, 0)), TransactionError::GasLimitTooHigh { limit: 5_001, maximum: 5_000 });
+        assert_eq!(rejected(&mut bc, transfer(0, 2_000, 51)),
+                   TransactionError::FeeNotPayable { account: "alice".into(), fee: 102_000 });
+
+        // The second transfer doesn't fit into the block anymore and has to wait
+        bc.submit_transaction(transfer(0, 4_000, 0)).unwrap();
+        bc.submit_transaction(transfer(1, 4_000, 0)).unwrap();
+        let mut block = bc.build_block_template();
+        assert_eq!(block.get_transaction_count(), 1);
+        block.mine(0);
+        bc.append_block(block).unwrap();
+        assert_eq!(bc.get_pending_transactions().len(), 1);
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(transfer(1, 4_000, 0));
+        block.add_transaction(transfer(2, 4_000, 0));
+        block.mine(0);
+        assert_eq!(bc.append_block(block).err(), Some(ChainError::BlockGasExceeded { block: 2, transaction: 1, limit: 5_000 }));
+        assert_eq!(bc.get_account_by_id("alice").unwrap().get_nonce(), 1);
+    }
+}
@@ -0,0 +1,120 @@
+use super::ChainError;
+use blake2::VarBlake2b;
+use blake2::digest::{Update, VariableOutput};
+use std::fmt;
+use std::str::FromStr;
+
+/// Amount of bytes of a hash
+pub const HASH_LENGTH: usize = 32;
+
+/// A 256 bit hash (BLAKE2b with 32 bytes output) as used for blocks and transactions
+/// It is displayed and parsed as 64 hex characters, so it can be copied around easily
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hash([u8; HASH_LENGTH]);
+
+impl Hash {
+    /// Will hash arbitrary data
+    pub fn digest(data: &[u8]) -> Self {
+        let mut hasher = VarBlake2b::new(HASH_LENGTH).unwrap();
+        hasher.update(data);
+
+        let mut bytes = [0u8; HASH_LENGTH];
+        hasher.finalize_variable(|result| bytes.copy_from_slice(result));
+        Hash(bytes)
+    }
+
+    /// Wraps already calculated hash bytes
+    pub fn from_bytes(bytes: [u8; HASH_LENGTH]) -> Self {
+        Hash(bytes)
+    }
+
+    /// The raw bytes of the hash
+    pub fn as_bytes(&self) -> &[u8; HASH_LENGTH] {
+        &self.0
+    }
+
+    /// Counts the zero bits at the beginning of the hash (used for Proof of Work)
+    pub fn leading_zero_bits(&self) -> u32 {
+        let mut zero_bits = 0;
+        for byte in self.0.iter() {
+            zero_bits += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+
+        zero_bits
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash({})", self)
+    }
+}
+
+impl FromStr for Hash {
+    type Err = ChainError;
+
+    /// Parses 64 hex characters (upper or lower case)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; HASH_LENGTH];
+        hex::decode_to_slice(s, &mut bytes)
+            .map_err(|_| ChainError::MalformedHash)?;
+
+        Ok(Hash(bytes))
+    }
+}
+
+/// Hashes are (de)serialized as hex string, just like they are displayed
+#[cfg(feature = "serde")]
+impl serde::Serialize for Hash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Hash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let hash = Hash::digest(b"rchain");
+        let parsed: Hash = hash.to_string().parse().unwrap();
+        assert_eq!(parsed, hash);
+        assert_eq!(hash.to_string().to_uppercase().parse::<Hash>(), Ok(hash));
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        assert!("abc".parse::<Hash>().is_err());
+        assert!("zz".repeat(HASH_LENGTH).parse::<Hash>().is_err());
+        assert!("00".repeat(HASH_LENGTH + 1).parse::<Hash>().is_err());
+    }
+
+    #[test]
+    fn leading_zero_bits() {
+        let mut bytes = [0xffu8; HASH_LENGTH];
+        assert_eq!(Hash::from_bytes(bytes).leading_zero_bits(), 0);
+
+        bytes[0] = 0;
+        bytes[1] = 0b0001_0000;
+        assert_eq!(Hash::from_bytes(bytes).leading_zero_bits(), 11);
+        assert_eq!(Hash::from_bytes([0; HASH_LENGTH]).leading_zero_bits(), 256);
+    }
+}
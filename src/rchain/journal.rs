@@ -0,0 +1,171 @@
+use super::{Account, AccountType, TransactionError, WorldState};
+use super::authority::keep_rejected_blocks;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Records how accounts looked before they were changed, so the changes can be reversed
+/// Every account is recorded only once (on its first change), so undoing restores the
+/// state from the moment the journal was started
+#[derive(Clone, Default)]
+pub struct Journal {
+    /// Previous version of every changed account (None if the account was created)
+    changes: Vec<(String, Option<Account>)>,
+
+    /// Ids of all accounts recorded so far
+    recorded: HashSet<String>,
+}
+
+impl Journal {
+    /// Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Will return the amount of accounts changed
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Will tell if no account was changed at all
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Will reverse all recorded changes
+    /// (except the rejected blocks counted for validators, no block records them)
+    pub fn undo(self, accounts: &mut HashMap<String, Account>) {
+        for (id, previous) in self.changes.into_iter().rev() {
+            match previous {
+                Some(mut account) => {
+                    if let Some(current) = accounts.get(&id) {
+                        keep_rejected_blocks(current, &mut account);
+                    }
+                    accounts.insert(id, account)
+                }
+                None => accounts.remove(&id),
+            };
+        }
+    }
+
+    fn record(&mut self, id: &str, previous: Option<&Account>) {
+        if self.recorded.insert(id.to_string()) {
+            self.changes.push((id.to_string(), previous.cloned()));
+        }
+    }
+}
+
+/// Lists the changed accounts only (the previous versions would be way too verbose)
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ids: Vec<&String> = self.changes.iter().map(|(id, _)| id).collect();
+        f.debug_struct("Journal").field("accounts", &ids).finish()
+    }
+}
+
+/// A world state which writes every change of the accounts into a journal
+/// (see `Journal::undo` to reverse them)
+pub struct JournaledState<'a> {
+    accounts: &'a mut HashMap<String, Account>,
+    journal: &'a mut Journal,
+}
+
+impl<'a> JournaledState<'a> {
+    /// Constructor
+    pub fn new(accounts: &'a mut HashMap<String, Account>, journal: &'a mut Journal) -> Self {
+        JournaledState { accounts, journal }
+    }
+}
+
+impl WorldState for JournaledState<'_> {
+    fn get_user_ids(&self) -> Vec<String> {
+        self.accounts.get_user_ids()
+    }
+
+    fn get_account_by_id_mut(&mut self, id: &str) -> Option<&mut Account> {
+        // Whoever asks for a mutable account will most likely change it
+        let account = self.accounts.get_mut(id)?;
+        self.journal.record(id, Some(account));
+        Some(account)
+    }
+
+    fn get_account_by_id(&self, id: &str) -> Option<&Account> {
+        self.accounts.get_account_by_id(id)
+    }
+
+    fn create_account(&mut self, id: String, account_type: AccountType) -> Result<(), TransactionError> {
+        if !self.accounts.contains_key(&id) {
+            self.journal.record(&id, None);
+        }
+
+        self.accounts.create_account(id, account_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_restores_changed_and_removes_created_accounts() {
+        let mut accounts = HashMap::new();
+        accounts.create_account("alice".into(), AccountType::User).unwrap();
+        let before = accounts.clone();
+
+        let mut journal = Journal::new();
+        let mut state = JournaledState::new(&mut accounts, &mut journal);
+        state.get_account_by_id_mut("alice").unwrap().tokens = 5;
+        state.get_account_by_id_mut("alice").unwrap().tokens = 7;
+        state.create_account("bob".into(), AccountType::User).unwrap();
+        assert!(state.create_account("alice".into(), AccountType::User).is_err());
+
+        assert_eq!(journal.len(), 2);
+        journal.undo(&mut accounts);
+        assert_eq!(accounts, before);
+    }
+
+    #[test]
+    fn rewind_restores_state_and_pending_transactions() {
+        use super::super::{Blockchain, Block, Transaction, TransactionData};
+
+        let path = std::env::temp_dir().join(format!("rchain-rewind-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut bc = Blockchain::new();
+        bc.open_store(&path).unwrap();
+
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new("alice".into(),
+                                                 TransactionData::CreateUserAccount("alice".into()), 0));
+        genesis.add_transaction(Transaction::new("alice".into(), TransactionData::CreateTokens {
+            receiver: "alice".into(), amount: 10 }, 0));
+        genesis.add_transaction(Transaction::new("bob".into(),
+                                                 TransactionData::CreateUserAccount("bob".into()), 0));
+        genesis.mine(0);
+        bc.append_block(genesis).unwrap();
+        let after_genesis = bc.accounts.clone();
+
+        for nonce in 1..3 {
+            bc.submit_transaction(Transaction::new("alice".into(), TransactionData::TransferTokens {
+                to: "bob".into(), amount: 3 }, nonce)).unwrap();
+            let mut block = bc.build_block_template();
+            block.mine(0);
+            bc.append_block(block).unwrap();
+        }
+        assert_eq!(bc.get_balance("bob"), Some(6));
+
+        let removed = bc.rewind_to(0).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(bc.len(), 1);
+        assert_eq!(bc.accounts, after_genesis);
+        assert_eq!(bc.get_pending_transactions().len(), 2);
+        assert!(bc.rewind_to(1).is_err());
+
+        // The store only contains the genesis block anymore
+        let mut reloaded = Blockchain::new();
+        reloaded.open_store(&path).unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded.accounts, after_genesis);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
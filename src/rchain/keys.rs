@@ -0,0 +1,105 @@
+use ed25519_dalek::{PublicKey, SecretKey, Signature, Signer, Verifier};
+use rand::rngs::OsRng;
+use super::ChainError;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A private / public key pair which is able to sign transactions
+/// The hex encoded public key is used as the AccountID of the owner,
+/// so everyone is able to check a signature by just knowing the account
+pub struct Keypair {
+    inner: ed25519_dalek::Keypair,
+}
+
+impl Keypair {
+    /// Will create a brand new random key pair using the operating systems randomness
+    pub fn generate() -> Self {
+        let mut csprng = OsRng {};
+        Keypair {
+            inner: ed25519_dalek::Keypair::generate(&mut csprng),
+        }
+    }
+
+    /// Will restore a key pair from its 32 bytes secret key
+    pub fn from_secret_bytes(bytes: &[u8]) -> Result<Self, ChainError> {
+        let secret = SecretKey::from_bytes(bytes)
+            .map_err(|_| ChainError::InvalidSecretKey)?;
+        let public: PublicKey = (&secret).into();
+
+        Ok(Keypair {
+            inner: ed25519_dalek::Keypair { secret, public },
+        })
+    }
+
+    /// Will return the secret part of the key (keep it safe!)
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.inner.secret.to_bytes()
+    }
+
+    /// The id of the account that belongs to that key (the hex encoded public key)
+    pub fn account_id(&self) -> String {
+        hex::encode(self.inner.public.as_bytes())
+    }
+
+    /// Signs an arbitrary message and returns the hex encoded signature
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(self.inner.sign(message).to_bytes().as_ref())
+    }
+}
+
+impl Clone for Keypair {
+    fn clone(&self) -> Self {
+        Keypair::from_secret_bytes(&self.secret_bytes()).unwrap()
+    }
+}
+
+impl fmt::Debug for Keypair {
+    // Never print out the secret part
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("account_id", &self.account_id())
+            .finish()
+    }
+}
+
+/// Checks if `signature` (hex) is a valid signature of `message` created by
+/// the owner of `account_id` (hex encoded public key)
+/// Malformed account ids or signatures are never valid
+pub fn verify_signature(account_id: &str, message: &[u8], signature: &str) -> bool {
+    let public = match hex::decode(account_id).ok()
+        .and_then(|bytes| PublicKey::from_bytes(&bytes).ok()) {
+        Some(public) => public,
+        None => return false,
+    };
+
+    let signature = match hex::decode(signature).ok()
+        .and_then(|bytes| Signature::try_from(&bytes[..]).ok()) {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    public.verify(message, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_belong_to_message_and_key() {
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"message");
+        assert!(verify_signature(&keypair.account_id(), b"message", &signature));
+
+        assert!(!verify_signature(&keypair.account_id(), b"massage", &signature));
+        assert!(!verify_signature(&Keypair::generate().account_id(), b"message", &signature));
+        assert!(!verify_signature("no hex", b"message", &signature));
+        assert!(!verify_signature(&keypair.account_id(), b"message", &signature[2..]));
+
+        // A restored key signs for the same account
+        let restored = Keypair::from_secret_bytes(&keypair.secret_bytes()).unwrap();
+        assert_eq!(restored.account_id(), keypair.account_id());
+        assert!(verify_signature(&keypair.account_id(), b"message", &restored.sign(b"message")));
+        assert_eq!(Keypair::from_secret_bytes(&[1, 2, 3]).err(), Some(ChainError::InvalidSecretKey));
+    }
+}
@@ -0,0 +1,295 @@
+use super::authority::check_validator;
+use super::{Blockchain, BlockHeader, ChainError, ChainParams, Hash, MerkleProof};
+
+/// A client which only keeps the block headers instead of whole blocks
+/// It checks the headers exactly like a full chain does (linkage, difficulty and work, and with
+/// Proof of Authority the validator and its signature), but it can't execute transactions.
+/// Instead, it relies on proofs that a transaction is part of a block
+/// (see `Blockchain::prove_transaction`)
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    /// Rules for the Proof of Work (have to be the same as the full chains' ones)
+    params: ChainParams,
+
+    /// Hash of the genesis block of the chain (see `ChainSpec::genesis_hash`)
+    genesis_hash: Hash,
+
+    /// The validators taking turns, sorted (only used with Proof of Authority, see `set_validators`)
+    validators: Vec<String>,
+
+    /// All accepted headers, starting with the genesis block
+    headers: Vec<BlockHeader>,
+
+    /// The hash of every accepted header (same order as `headers`)
+    hashes: Vec<Hash>,
+}
+
+/// Claims that a transaction is part of a block
+/// The claim can be checked by anyone knowing the blocks' header
+#[derive(Debug, Clone, PartialEq)]
+pub struct InclusionProof {
+    /// Hash of the block containing the transaction
+    pub block_hash: Hash,
+
+    /// Hash of the transaction itself
+    pub transaction_hash: Hash,
+
+    /// Path from the transaction to the Merkle root of the block
+    pub merkle_proof: MerkleProof,
+}
+
+impl LightClient {
+    /// Constructor
+    /// Only the chain starting with the given genesis block is accepted
+    pub fn new(params: ChainParams, genesis_hash: Hash) -> Self {
+        LightClient {
+            params,
+            genesis_hash,
+            validators: Vec::new(),
+            headers: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Will set the validators of a chain with Proof of Authority (see `Blockchain::get_validators`)
+    /// They are created by the genesis block and never change, so they are only set once
+    pub fn set_validators(&mut self, mut validators: Vec<String>) {
+        validators.sort();
+        self.validators = validators;
+    }
+
+    /// Will add the header of the next block if it is connected to the last header,
+    /// uses the right difficulty and the work was done (the first header has to be the genesis block)
+    /// With Proof of Authority the header has to name the validator whose turn it is,
+    /// and `signature` has to be that validators' signature of the block
+    pub fn append_header(&mut self, header: BlockHeader, signature: Option<&String>) -> Result<(), ChainError> {
+        let height = self.headers.len();
+        self.headers.push(header);
+
+        let mut result = self.params.check_header(&self.headers, height);
+        if result.is_ok() && height == 0 {
+            let actual = self.headers[0].calculate_hash();
+            if actual != self.genesis_hash {
+                result = Err(ChainError::GenesisMismatch { expected: self.genesis_hash, actual });
+            }
+        }
+        if result.is_ok() && self.params.proof_of_authority && height > 0 {
+            result = check_validator(&self.headers[height], signature, &self.validators, height);
+        }
+
+        if let Err(err) = result {
+            self.headers.pop();
+            return Err(err);
+        }
+
+        let hash = self.headers.last().unwrap().calculate_hash();
+        self.hashes.push(hash);
+        Ok(())
+    }
+
+    /// Will fetch all headers from a full chain which are not known yet
+    pub fn sync_from(&mut self, chain: &Blockchain) -> Result<(), ChainError> {
+        for block in chain.blocks.iter().skip(self.len()) {
+            self.append_header(block.get_header().clone(), block.get_signature())?;
+        }
+
+        Ok(())
+    }
+
+    /// Will return the amount of headers stored
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Will tell if there are no headers stored at all
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Will return the hash of the last header
+    pub fn get_last_block_hash(&self) -> Option<Hash> {
+        self.hashes.last().copied()
+    }
+
+    /// Will return the header at the given height (0 is the genesis block)
+    pub fn get_header(&self, height: usize) -> Option<&BlockHeader> {
+        self.headers.get(height)
+    }
+
+    /// Will return the height of the block with the given hash (if known)
+    pub fn get_height(&self, block_hash: &Hash) -> Option<usize> {
+        self.hashes.iter().position(|hash| hash == block_hash)
+    }
+
+    /// Checks if the proven transaction is part of a block we know
+    pub fn verify_inclusion(&self, proof: &InclusionProof) -> bool {
+        match self.get_height(&proof.block_hash) {
+            Some(height) => proof.merkle_proof.verify(&proof.transaction_hash,
+                                                      &self.headers[height].get_tx_root()),
+            None => false,
+        }
+    }
+}
+
+impl Blockchain {
+    /// Will create a proof that the transaction with the given hash is part of the chain
+    /// Returns None if no block contains it
+    pub fn prove_transaction(&self, transaction_hash: &Hash) -> Option<InclusionProof> {
+        for block in self.blocks.iter() {
+            let hashes = block.get_transaction_hashes();
+            if let Some(index) = hashes.iter().position(|hash| hash == transaction_hash) {
+                return Some(InclusionProof {
+                    block_hash: block.calculate_hash(),
+                    transaction_hash: *transaction_hash,
+                    merkle_proof: block.prove_transaction(index)?,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Block, ChainSpec, Keypair, Transaction, TransactionData};
+    use super::*;
+
+    fn chain(proof_of_authority: bool, keys: &[Keypair]) -> Blockchain {
+        let validators: String = keys.iter()
+            .map(|key| format!("[accounts.{}]\ntype = \"validator\"\n", key.account_id()))
+            .collect();
+        let spec = ChainSpec::parse(&format!("chain_id = \"light\"\ninitial_difficulty = 2\n\
+                                              proof_of_authority = {}\n[accounts.alice]\n{}",
+                                             proof_of_authority, validators)).unwrap();
+        Blockchain::from_spec(&spec).unwrap()
+    }
+
+    fn block(bc: &Blockchain) -> Block {
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+            key: "k".into(), value: bc.len().to_string() }, bc.len() as u128 - 1));
+        block
+    }
+
+    #[test]
+    fn checks_headers_and_inclusion() {
+        let mut bc = chain(false, &[]);
+        for _ in 0..2 {
+            let mut block = block(&bc);
+            block.mine(bc.get_difficulty());
+            bc.append_block(block).unwrap();
+        }
+
+        let mut light_client = LightClient::new(bc.get_params().clone(), bc.get_genesis_hash().unwrap());
+        light_client.sync_from(&bc).unwrap();
+        assert_eq!(light_client.len(), 3);
+        assert_eq!(light_client.get_last_block_hash(), bc.get_last_block_hash());
+
+        let transaction = bc.blocks[2].transactions[0].calculate_hash();
+        let proof = bc.prove_transaction(&transaction).unwrap();
+        assert!(light_client.verify_inclusion(&proof));
+        assert!(!light_client.verify_inclusion(&InclusionProof { transaction_hash: Hash::digest(b"other"), ..proof }));
+
+        // Headers which don't continue the chain are rejected
+        let mut header = bc.blocks[2].get_header().clone();
+        header.nonce += 1;
+        assert!(light_client.append_header(header, None).is_err());
+        assert_eq!(light_client.len(), 3);
+    }
+
+    #[test]
+    fn header_alone_identifies_the_block() {
+        let mut bc = chain(false, &[]);
+        let mut light_client = LightClient::new(bc.get_params().clone(), bc.get_genesis_hash().unwrap());
+
+        // A chain has to start with a genesis block
+        let mut next = block(&bc);
+        next.mine(bc.get_difficulty());
+        assert_eq!(light_client.append_header(next.get_header().clone(), None).err(),
+                   Some(ChainError::GenesisHasParent));
+
+        // And it has to be the genesis block the client was made for
+        let other = ChainSpec::parse("chain_id = \"other\"\ninitial_difficulty = 2").unwrap().genesis_block();
+        assert_eq!(light_client.append_header(other.get_header().clone(), None).err(),
+                   Some(ChainError::GenesisMismatch {
+                       expected: bc.get_genesis_hash().unwrap(),
+                       actual: other.calculate_hash(),
+                   }));
+        assert!(light_client.is_empty());
+        light_client.sync_from(&bc).unwrap();
+
+        // Every rule of the full chain applies to the header
+        let mut easy = block(&bc);
+        easy.mine(0);
+        assert_eq!(light_client.append_header(easy.get_header().clone(), None).err(),
+                   Some(ChainError::DifficultyMismatch { block: 1, expected: 2, actual: 0 }));
+
+        let mut unlinked = Block::new(None);
+        unlinked.mine(bc.get_difficulty());
+        assert_eq!(light_client.append_header(unlinked.get_header().clone(), None).err(),
+                   Some(ChainError::MissingPrevHash { block: 1 }));
+
+        let mut elsewhere = Block::new(Some(Hash::digest(b"other")));
+        elsewhere.mine(bc.get_difficulty());
+        assert_eq!(light_client.append_header(elsewhere.get_header().clone(), None).err(),
+                   Some(ChainError::BrokenLinkage {
+                       block: 1,
+                       expected: bc.get_last_block_hash(),
+                       actual: Some(Hash::digest(b"other")),
+                   }));
+        assert_eq!(light_client.len(), 1);
+
+        // The header commits to the transactions, so it has the hash of the whole block
+        light_client.append_header(next.get_header().clone(), None).unwrap();
+        assert_eq!(next.get_header().calculate_hash(), next.calculate_hash());
+        assert_eq!(light_client.get_last_block_hash(), next.get_hash());
+        assert_eq!(light_client.get_header(1).unwrap().get_tx_root(), next.get_header().get_tx_root());
+
+        let mut altered = next.clone();
+        altered.transactions[0].nonce = 5;
+        assert!(!altered.verify_own_hash());
+        bc.append_block(next).unwrap();
+        let proof = bc.prove_transaction(&bc.blocks[1].transactions[0].calculate_hash()).unwrap();
+        assert!(!light_client.verify_inclusion(&InclusionProof {
+            transaction_hash: altered.transactions[0].calculate_hash(),
+            ..proof
+        }));
+    }
+
+    #[test]
+    fn checks_validators_with_proof_of_authority() {
+        let mut keys = vec![Keypair::generate(), Keypair::generate()];
+        keys.sort_by_key(|key| key.account_id());
+        let mut bc = chain(true, &keys);
+        let mut sealed = block(&bc);
+        sealed.seal(&keys[1], bc.get_difficulty());
+        bc.append_block(sealed).unwrap();
+
+        // Without knowing the validators, nothing after the genesis block can be checked
+        let mut light_client = LightClient::new(bc.get_params().clone(), bc.get_genesis_hash().unwrap());
+        assert_eq!(light_client.sync_from(&bc).err(), Some(ChainError::NoValidators { block: 1 }));
+        light_client.set_validators(bc.get_validators());
+        light_client.sync_from(&bc).unwrap();
+        assert_eq!(light_client.len(), 2);
+
+        // Not its turn
+        let mut wrong = block(&bc);
+        wrong.seal(&keys[1], bc.get_difficulty());
+        assert_eq!(light_client.append_header(wrong.get_header().clone(), wrong.get_signature()).err(),
+                   Some(ChainError::WrongValidator {
+                       block: 2, expected: keys[0].account_id(), actual: Some(keys[1].account_id()) }));
+
+        // Unsigned or forged
+        let mut forged = block(&bc);
+        forged.seal(&keys[0], bc.get_difficulty());
+        let signature = keys[1].sign(forged.calculate_hash().as_bytes());
+        assert_eq!(light_client.append_header(forged.get_header().clone(), None).map_err(|err| err.code()),
+                   Err(8820392));
+        assert_eq!(light_client.append_header(forged.get_header().clone(), Some(&signature)).map_err(|err| err.code()),
+                   Err(8820392));
+
+        light_client.append_header(forged.get_header().clone(), forged.get_signature()).unwrap();
+        assert_eq!(light_client.len(), 3);
+    }
+}
@@ -0,0 +1,288 @@
+use super::{Blockchain, Block, Transaction, Account, Hash, ChainError, ExecutionContext, Journal, JournaledState,
+            TransactionError};
+use std::collections::HashMap;
+
+/// The mempool: transactions which are waiting to be included into a block
+impl Blockchain {
+    /// Will add a transaction to the pending transactions
+    /// The transaction has to be executable against the current world state
+    /// (after all transactions already pending), otherwise it is rejected
+    /// This includes contract calls: a call which would fail is rejected (so its gas isn't lost)
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<(), ChainError> {
+        if self.require_signatures && !transaction.check_signature() {
+            return Err(ChainError::UnsignedTransaction { account: transaction.from.clone() });
+        }
+
+        let hash = transaction.calculate_hash();
+        if self.pending_transactions.iter().any(|pending| pending.calculate_hash() == hash) {
+            return Err(ChainError::AlreadyPending { transaction: hash });
+        }
+
+        // A transaction which doesn't fit into any block would be pending forever
+        if transaction.gas_limit > self.params.block_gas_limit {
+            return Err(ChainError::TransactionRejected {
+                account: transaction.from.clone(),
+                error: TransactionError::GasLimitTooHigh {
+                    limit: transaction.gas_limit,
+                    maximum: self.params.block_gas_limit,
+                },
+            });
+        }
+
+        // Try it out on top of the pending transactions (only their state is kept, so just
+        // this transaction is executed). A rejected transaction leaves no changes behind
+        let mut state = match self.pending_state.take() {
+            Some(state) => state,
+            None => self.execute_pending_transactions(u64::MAX).0,
+        };
+
+        let mut journal = Journal::new();
+        let result = transaction.execute(&mut JournaledState::new(&mut state, &mut journal),
+                                         &ExecutionContext::pending(self.is_empty()));
+        let error = match result {
+            Ok(receipt) => receipt.error,
+            Err(err) => Some(err),
+        };
+        if error.is_some() {
+            journal.undo(&mut state);
+        }
+        self.pending_state = Some(state);
+
+        if let Some(error) = error {
+            return Err(ChainError::TransactionRejected { account: transaction.from.clone(), error });
+        }
+
+        self.pending_transactions.push(transaction);
+        Ok(())
+    }
+
+    /// Will return all transactions waiting for a block (in order of submission)
+    pub fn get_pending_transactions(&self) -> &[Transaction] {
+        &self.pending_transactions
+    }
+
+    /// Will return the nonce the next transaction of the account has to use
+    /// (taking the pending transactions into account). None if the account does not exist
+    pub fn next_nonce(&self, account: &str) -> Option<u128> {
+        let pending = self.pending_transactions.iter()
+            .filter(|transaction| transaction.from == account)
+            .count() as u128;
+        Some(self.accounts.get(account)?.nonce + pending)
+    }
+
+    /// Will remove a pending transaction given its hash
+    /// and return it if it was pending at all
+    pub fn remove_pending_transaction(&mut self, hash: &Hash) -> Option<Transaction> {
+        let position = self.pending_transactions.iter()
+            .position(|transaction| transaction.calculate_hash() == *hash)?;
+
+        // Later transactions may depend on it, so the pending state has to be rebuilt
+        self.pending_state = None;
+        Some(self.pending_transactions.remove(position))
+    }
+
+    /// Will create a new block pointing to the last block which contains all pending transactions
+    /// which are (still) valid and fit into the gas limit of a block. The block is not mined yet
+    /// (see `Block::mine`)
+    /// The included transactions are removed from the pending transactions as soon as the
+    /// block is appended. If no transaction is valid, the block will be empty (and rejected by the chain)
+    pub fn build_block_template(&self) -> Block {
+        let (_, valid_transactions) = self.execute_pending_transactions(self.params.block_gas_limit);
+
+        let mut block = Block::new(self.get_last_block_hash());
+        for transaction in valid_transactions {
+            block.add_transaction(transaction);
+        }
+
+        block
+    }
+
+    /// Like `build_block_template`, but the block starts with a coinbase paying the full
+    /// reward to `producer` (who also receives the fees)
+    /// The coinbase is never signed (it has no sender), even on chains requiring signatures
+    pub fn build_block_template_for(&self, producer: String) -> Block {
+        let (_, valid_transactions) = self.execute_pending_transactions(self.params.block_gas_limit);
+
+        let mut block = Block::new(self.get_last_block_hash());
+        block.add_transaction(Transaction::coinbase(producer, self.params.reward_at(self.len())));
+        for transaction in valid_transactions {
+            block.add_transaction(transaction);
+        }
+
+        block
+    }
+
+    /// Will execute all pending transactions against a scratch copy of the world state
+    /// Returns that state and the transactions which could be executed successfully
+    /// Transactions whose gas limit doesn't fit into what is left of `gas_limit` are skipped
+    fn execute_pending_transactions(&self, gas_limit: u64) -> (HashMap<String, Account>, Vec<Transaction>) {
+        let mut state = self.accounts.clone();
+        let mut valid_transactions = Vec::new();
+        let mut gas_used: u64 = 0;
+
+        for transaction in self.pending_transactions.iter() {
+            if gas_used.saturating_add(transaction.gas_limit) > gas_limit {
+                continue;
+            }
+
+            // Every transaction gets its own journal, so a failing one won't leave
+            // any partial changes within the state
+            let mut journal = Journal::new();
+            let result = transaction.execute(&mut JournaledState::new(&mut state, &mut journal),
+                                             &ExecutionContext::pending(self.is_empty()));
+            match result {
+                Ok(receipt) => {
+                    gas_used = gas_used.saturating_add(receipt.gas_used);
+                    valid_transactions.push(transaction.clone());
+                }
+                Err(_) => journal.undo(&mut state),
+            }
+        }
+
+        (state, valid_transactions)
+    }
+
+    /// Will remove all pending transactions which are included in the last block or
+    /// can never be valid again (their nonce is used up)
+    pub(super) fn prune_pending_transactions(&mut self) {
+        self.pending_state = None;
+        let accounts = &self.accounts;
+        let included: Vec<Hash> = self.blocks.last()
+            .map(|block| block.transactions.iter()
+                .map(|transaction| transaction.calculate_hash())
+                .collect())
+            .unwrap_or_default();
+
+        self.pending_transactions.retain(|transaction| {
+            let nonce_used = accounts.get(&transaction.from)
+                .map(|account| transaction.nonce < account.nonce)
+                .unwrap_or(false);
+
+            !nonce_used && !included.contains(&transaction.calculate_hash())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ChainParams, TransactionData, TransactionError, WorldState};
+    use super::*;
+
+    fn chain() -> Blockchain {
+        let mut bc = Blockchain::new();
+        bc.set_params(ChainParams { initial_reward: 50, halving_interval: 2, ..ChainParams::default() });
+
+        let mut genesis = Block::new(None);
+        for user in ["alice", "bob"].iter() {
+            genesis.add_transaction(Transaction::new(user.to_string(),
+                                                     TransactionData::CreateUserAccount(user.to_string()), 0));
+        }
+        genesis.add_transaction(Transaction::new("alice".into(), TransactionData::CreateTokens {
+            receiver: "alice".into(), amount: 10 }, 0));
+        genesis.mine(0);
+        bc.append_block(genesis).unwrap();
+        bc
+    }
+
+    #[test]
+    fn producer_gets_reward_and_fees() {
+        let mut bc = chain();
+        let mut transfer = Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 4 }, 1);
+        transfer.set_fee(2);
+        bc.submit_transaction(transfer).unwrap();
+
+        let mut block = bc.build_block_template_for("bob".into());
+        block.mine(0);
+        bc.append_block(block).unwrap();
+
+        assert_eq!(bc.get_balance("alice"), Some(4));
+        assert_eq!(bc.get_balance("bob"), Some(50 + 4 + 2));
+
+        // Every coinbase uses nonce 0, which doesn't count as a replay
+        let mut block = bc.build_block_template_for("bob".into());
+        block.mine(0);
+        bc.append_block(block).unwrap();
+        assert_eq!(bc.get_balance("bob"), Some(56 + 25));
+        assert!(bc.check_full_validity().is_ok());
+
+        // The reward halves every two blocks
+        assert_eq!(bc.get_params().reward_at(2), 25);
+        assert_eq!(bc.get_params().reward_at(300), 0);
+    }
+
+    #[test]
+    fn coinbase_rules() {
+        let mut bc = chain();
+        let append = |bc: &mut Blockchain, transactions: Vec<Transaction>| {
+            let mut block = Block::new(bc.get_last_block_hash());
+            for transaction in transactions {
+                block.add_transaction(transaction);
+            }
+            block.mine(0);
+            bc.append_block(block).map_err(|err| match err {
+                ChainError::TransactionFailed { error, .. } => error,
+                err => panic!("unexpected {}", err),
+            })
+        };
+
+        let store = Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+            key: "k".into(), value: "v".into() }, 1);
+
+        assert_eq!(append(&mut bc, vec![Transaction::coinbase("bob".into(), 51)]),
+                   Err(TransactionError::CoinbaseTooLarge { amount: 51, reward: 50 }));
+        assert_eq!(append(&mut bc, vec![store.clone(), Transaction::coinbase("bob".into(), 1)]),
+                   Err(TransactionError::CoinbaseNotFirst));
+        assert_eq!(append(&mut bc, vec![Transaction::coinbase("bob".into(), 1),
+                                        Transaction::coinbase("bob".into(), 1)]),
+                   Err(TransactionError::CoinbaseNotFirst));
+        assert!(bc.submit_transaction(Transaction::coinbase("bob".into(), 1)).is_err());
+
+        let mut expensive = store;
+        expensive.set_fee(11);
+        assert_eq!(append(&mut bc, vec![expensive]),
+                   Err(TransactionError::FeeNotPayable { account: "alice".into(), fee: 11 }));
+
+        assert_eq!(append(&mut bc, vec![Transaction::coinbase("bob".into(), 50)]), Ok(()));
+        assert_eq!(bc.get_balance("bob"), Some(50));
+    }
+
+    #[test]
+    fn submissions_build_on_the_pending_state() {
+        let mut bc = chain();
+        let transfer = |amount, nonce| Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount }, nonce);
+
+        bc.submit_transaction(transfer(6, 1)).unwrap();
+        assert!(bc.submit_transaction(transfer(6, 2)).is_err());
+        // The rejected transfer left nothing behind, so the rest of the tokens can still be sent
+        bc.submit_transaction(transfer(4, 2)).unwrap();
+        assert_eq!(bc.next_nonce("alice"), Some(3));
+
+        // Without the first transfer, the second one may spend more
+        let first = bc.get_pending_transactions()[0].calculate_hash();
+        bc.remove_pending_transaction(&first).unwrap();
+        assert!(bc.submit_transaction(transfer(6, 2)).is_err());
+        bc.remove_pending_transaction(&bc.get_pending_transactions()[0].calculate_hash()).unwrap();
+        bc.submit_transaction(transfer(6, 1)).unwrap();
+
+        // After a block the pending state starts from the new accounts
+        let mut block = bc.build_block_template_for("bob".into());
+        block.mine(0);
+        bc.append_block(block).unwrap();
+        assert!(bc.submit_transaction(transfer(5, 2)).is_err());
+        bc.submit_transaction(transfer(4, 2)).unwrap();
+
+        // And after a rewind from the old ones again (the transfers are pending again)
+        bc.rewind_to(0).unwrap();
+        assert_eq!(bc.get_pending_transactions().len(), 2);
+        assert_eq!(bc.get_balance("alice"), Some(10));
+        assert!(bc.submit_transaction(transfer(1, 3)).is_err());
+
+        let mut block = bc.build_block_template_for("bob".into());
+        block.mine(0);
+        bc.append_block(block).unwrap();
+        assert_eq!(bc.get_balance("alice"), Some(0));
+        assert!(bc.check_full_validity().is_ok());
+    }
+}
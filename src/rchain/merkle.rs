@@ -0,0 +1,142 @@
+use super::{Hash, HASH_LENGTH};
+
+/// Prefix of hashed leaves (distinguishes leaves from inner nodes,
+/// so nobody can pass off an inner node as a transaction)
+const LEAF_PREFIX: u8 = 0;
+
+/// Prefix of hashed inner nodes
+const NODE_PREFIX: u8 = 1;
+
+/// Proves that a single transaction is part of a block without needing the whole block
+/// It contains the sibling of every node on the path from the transactions' leaf up to the root
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleProof {
+    /// Position of the transaction within the block
+    pub index: usize,
+
+    /// Siblings from the bottom to the top. The flag tells if the sibling is the left node
+    /// (Levels where the node has no sibling are skipped)
+    pub siblings: Vec<(Hash, bool)>,
+}
+
+/// Will calculate the Merkle root over the given transaction hashes
+/// If a level has an odd number of nodes, the last one is moved up unchanged
+/// (instead of pairing it with itself like Bitcoin does, which allows to create two
+/// different lists of transactions with the same root). No transactions result in a zero hash
+pub fn merkle_root(transaction_hashes: &[Hash]) -> Hash {
+    if transaction_hashes.is_empty() {
+        return Hash::from_bytes([0; HASH_LENGTH]);
+    }
+
+    let mut level: Vec<Hash> = transaction_hashes.iter().map(hash_leaf).collect();
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| if pair.len() == 2 { hash_nodes(&pair[0], &pair[1]) } else { pair[0] })
+            .collect();
+    }
+
+    level[0]
+}
+
+/// Will create the proof for the transaction at `index`
+/// Returns None if there is no such transaction
+pub fn merkle_proof(transaction_hashes: &[Hash], index: usize) -> Option<MerkleProof> {
+    if index >= transaction_hashes.len() {
+        return None;
+    }
+
+    let mut siblings = Vec::new();
+    let mut position = index;
+    let mut level: Vec<Hash> = transaction_hashes.iter().map(hash_leaf).collect();
+
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if sibling < level.len() {
+            siblings.push((level[sibling], sibling < position));
+        }
+
+        level = level.chunks(2)
+            .map(|pair| if pair.len() == 2 { hash_nodes(&pair[0], &pair[1]) } else { pair[0] })
+            .collect();
+        position /= 2;
+    }
+
+    Some(MerkleProof { index, siblings })
+}
+
+impl MerkleProof {
+    /// Will calculate the root the transaction would lead to with that proof
+    pub fn compute_root(&self, transaction_hash: &Hash) -> Hash {
+        let mut node = hash_leaf(transaction_hash);
+        for (sibling, sibling_is_left) in self.siblings.iter() {
+            node = if *sibling_is_left {
+                hash_nodes(sibling, &node)
+            } else {
+                hash_nodes(&node, sibling)
+            };
+        }
+
+        node
+    }
+
+    /// Checks if the transaction is part of the block having the given Merkle root
+    pub fn verify(&self, transaction_hash: &Hash, root: &Hash) -> bool {
+        self.compute_root(transaction_hash) == *root
+    }
+}
+
+fn hash_leaf(transaction_hash: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(1 + HASH_LENGTH);
+    data.push(LEAF_PREFIX);
+    data.extend_from_slice(transaction_hash.as_bytes());
+    Hash::digest(&data)
+}
+
+fn hash_nodes(left: &Hash, right: &Hash) -> Hash {
+    let mut data = Vec::with_capacity(1 + 2 * HASH_LENGTH);
+    data.push(NODE_PREFIX);
+    data.extend_from_slice(left.as_bytes());
+    data.extend_from_slice(right.as_bytes());
+    Hash::digest(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(count: usize) -> Vec<Hash> {
+        (0..count).map(|i| Hash::digest(&i.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn proofs_verify_for_every_transaction() {
+        for count in 1..=9 {
+            let hashes = hashes(count);
+            let root = merkle_root(&hashes);
+
+            for (index, hash) in hashes.iter().enumerate() {
+                let proof = merkle_proof(&hashes, index).unwrap();
+                assert!(proof.verify(hash, &root), "count {} index {}", count, index);
+            }
+        }
+    }
+
+    #[test]
+    fn proof_rejects_other_transaction() {
+        let hashes = hashes(5);
+        let root = merkle_root(&hashes);
+        let proof = merkle_proof(&hashes, 2).unwrap();
+
+        assert!(!proof.verify(&hashes[3], &root));
+        assert!(merkle_proof(&hashes, 5).is_none());
+    }
+
+    #[test]
+    fn odd_node_is_not_duplicated() {
+        let three = hashes(3);
+        let mut four = three.clone();
+        four.push(three[2]);
+
+        assert_ne!(merkle_root(&three), merkle_root(&four));
+    }
+}
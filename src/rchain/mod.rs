@@ -1,488 +1,1784 @@
-extern crate blake2;
-
-use std::vec::Vec;
-use std::collections::HashMap;
-use std::convert::Into;
-use std::time::SystemTime;
-use blake2::{Blake2b, Digest};
-use std::string::String;
-use std::convert::From;
-
-
-/// The actual Blockchain container
-#[derive(Debug, Clone)]
-pub struct Blockchain {
-    /// Stores all the blocks which are accepted already within the blockchain
-    pub blocks: Vec<Block>,
-
-    /// Lookup from AccountID (will be a public key later) to Account.
-    /// Effectively, this represents the WorldState
-    pub accounts: HashMap<String, Account>,
-
-    /// Will store transactions which should be added to the chain
-    /// but aren't yet
-    pending_transactions: Vec<Transaction>,
-}
-
-/// Represents the current state of the blockchain after all Blocks are executed
-/// A world state is technically not necessary since we always could build the information
-/// by iterating through all the blocks. Generally, this doesn't seem like a good option
-/// However, we do not force the actual Blockchain to implement a WorldState but rather
-/// behave like having one. This trait therefore just defines an expected interface into our Blockchain
-/// (Actually it doesn't even care if we the information is stored within a blockchain)
-pub trait WorldState {
-    /// Will bring us all registered user ids
-    fn get_user_ids(&self) -> Vec<String>;
-
-    /// Will return an account given it id if is available (mutable)
-    fn get_account_by_id_mut(&mut self, id: &String) -> Option<&mut Account>;
-
-    /// Will return an account given it id if is available
-    fn get_account_by_id(&self, id: &String) -> Option<&Account>;
-
-    /// Will add a new account
-    fn create_account(&mut self, id: String, account_type: AccountType) -> Result<(), &'static str>;
-}
-
-/// One single part of the blockchain.
-/// Basically contains a list of transactions
-#[derive(Clone, Debug)]
-pub struct Block {
-    /// Actions that this block includes
-    /// There has to be at least one
-    pub(crate) transactions: Vec<Transaction>,
-
-    /// This actually connects the blocks together
-    prev_hash: Option<String>,
-
-    /// We store the hash of the block here also in order to
-    /// save the last block from being tampered with later on
-    hash: Option<String>,
-
-    /// Some arbitrary number which will be later used for Proof of Work
-    nonce: u128,
-}
-
-/// Stores a request to the blockchain
-#[derive(Clone, Debug)]
-pub struct Transaction {
-    /// Unique number (will be used for randomization later; prevents replay attacks)
-    nonce: u128,
-
-    /// Account ID
-    from: String,
-
-    /// Stores the time the transaction was created
-    created_at: SystemTime,
-
-    /// the type of the transaction and its additional information
-    pub(crate) record: TransactionData,
-
-    /// Signature of the hash of the whole message
-    signature: Option<String>,
-}
-
-/// A single operation to be stored on the chain
-/// Noticeable, enums in rust actually can carry data in a
-/// tuple-like structure (CreateUserAccount) or a dictionary-like (the ChangeStoreValue)
-#[derive(Clone, Debug, PartialEq)]
-pub enum TransactionData {
-    /// Will be used to store a new user account
-    CreateUserAccount(String),
-
-    /// Will be used to change or create a arbitrary value into an account
-    ChangeStoreValue { key: String, value: String },
-
-    /// Will be used to move tokens from one owner to another
-    TransferTokens { to: String, amount: u128 },
-
-    /// Just create tokens out of nowhere
-    CreateTokens { receiver: String, amount: u128 },
-
-    // ... Extend it as you wish, you get the idea
-}
-
-/// Represents an account on the blockchain
-/// This is basically the primary part of the "world state" of the blockchain
-/// It is the final status after performing all blocks in order
-#[derive(Clone, Debug)]
-pub struct Account {
-    /// We want the account to be able to store any information we want (Dictionary)
-    store: HashMap<String, String>,
-
-    /// store if this is a user account or sth else
-    acc_type: AccountType,
-
-    /// Amount of tokens that account owns (like BTC or ETH)
-    tokens: u128,
-}
-
-/// We can support different types of accounts
-/// which could be used to represent different roles within the system
-/// This is just for later extension, for now we will only use User accounts
-#[derive(Clone, Debug)]
-pub enum AccountType {
-    /// A common user account
-    User,
-
-    /// An account that technically does not represent an individual
-    /// Think of this like a SmartContract in Ethereum. We will not use it
-    /// in our implementation. It's just here if you want to go on implementing
-    /// to provide a starting point for more :)
-    Contract,
-
-    /// Add whatever roles you need.
-    /// Again, we will NOT make use of this for the example here
-    Validator {
-        // Again, enum members in rust may store additional data
-        correctly_validated_blocks: u128,
-        incorrectly_validated_blocks: u128,
-        you_get_the_idea: bool,
-    },
-}
-
-impl Blockchain {
-    /// Constructor
-    pub fn new() -> Self {
-        Blockchain {
-            blocks: Vec::new(),
-            accounts: HashMap::new(),
-            pending_transactions: Vec::new(),
-        }
-    }
-
-    /// Will add a block to the Blockchain
-    /// @TODO every simple step could be refactored into a separate function for
-    /// better testability and code-reusability
-    pub fn append_block(&mut self, block: Block) -> Result<(), String> {
-
-        // The genesis block may create user out of nowhere,
-        // and also may do some other things
-        let is_genesis = self.len() == 0;
-
-        // Check if the hash matches the transactions
-        if !block.verify_own_hash() {
-            return Err("The block hash is mismatching! (Code: 93820394)".into());
-        }
-
-        // Check if the newly added block is meant to be appended onto the last block
-        if !(block.prev_hash == self.get_last_block_hash()) {
-            return Err("The new block has to point to the previous block (Code: 3948230)".into());
-        }
-
-        // There has to be at least one transaction inside the queue
-        if block.get_transaction_count() == 0 {
-            return Err("There has to be at least one transaction \
-            inside the block! (Code: 9482930)".into());
-        }
-
-        // Reject block having nonces that are already used (Prevent reply attacks etc.)
-        // @Todo (Will skip that for simplicity)
-
-
-        // This is expensive and just used for rollback if some transactions succeed whilst
-        // others don't (prevent inconsistent states)
-        // Arguably, that could be implemented more resource-aware
-        let old_state = self.accounts.clone();
-
-        // Execute each transaction
-        for (i, transaction) in block.transactions.iter().enumerate() {
-
-            // Execute the transaction
-            if let Err(err) = transaction.execute(self, &is_genesis) {
-                // Recover state on failure
-                self.accounts = old_state;
-
-                // ... and reject the block
-                return Err(format!("Could not execute transaction {} due to `{}`. Rolling back \
-                (Code: 38203984)", i + 1, err));
-            }
-        }
-
-        // Everything went fine... append the block
-        self.blocks.push(block);
-
-        Ok(())
-    }
-
-    /// Will return the amount of blocks currently stored
-    pub fn len(&self) -> usize {
-        self.blocks.len()
-    }
-
-    /// Will return the hash of the last block
-    pub fn get_last_block_hash(&self) -> Option<String> {
-        if self.len() == 0 {
-            return None;
-        }
-
-        self.blocks[self.len() - 1].hash.clone()
-    }
-
-    /// Checks if the blockchain was tempered with
-    /// It will check until the first error happens and return a description of the problem
-    /// if everything is fine it will return Ok
-    pub fn check_validity(&self) -> Result<(), String> {
-        for (block_num, block) in self.blocks.iter().enumerate() {
-
-            // Check if block saved hash matches to calculated hash
-            if !block.verify_own_hash() {
-                return Err(format!("Stored hash for Block #{} \
-                    does not match calculated hash (Code: 665234234)", block_num + 1).into());
-            }
-
-            // Check previous black hash points to actual previous block
-            if block_num == 0 {
-                // Genesis block should point to nowhere
-                if block.prev_hash.is_some() {
-                    return Err("The genesis block has a previous hash set which \
-                     it shouldn't Code :394823098".into());
-                }
-            } else {
-                // Non genesis blocks should point to previous blocks hash (which is validated before)
-                if block.prev_hash.is_none() {
-                    return Err(format!("Block #{} has no previous hash set", block_num + 1).into());
-                }
-
-                // Store the values locally to use them within the error message on failure
-                let prev_hash_proposed = block.prev_hash.as_ref().unwrap();
-                let prev_hash_actual = self.blocks[block_num - 1].hash.as_ref().unwrap();
-
-                if !(&block.prev_hash == &self.blocks[block_num - 1].hash) {
-                    return Err(format!("Block #{} is not connected to previous block (Hashes do \
-                    not match. Should be `{}` but is `{}`)", block_num, prev_hash_proposed,
-                                       prev_hash_actual).into());
-                }
-            }
-
-            // Check if transactions are signed correctly
-            for (transaction_num, transaction) in block.transactions.iter().enumerate() {
-
-                // Careful! With that implementation an unsigned message will always
-                // be valid! You may remove the first check to only accept signed transactions
-                if transaction.is_signed() && !transaction.check_signature() {
-                    return Err(format!("Transaction #{} for Block #{} has an invalid signature \
-                    (Code: 4398239048)", transaction_num + 1, block_num + 1));
-                }
-            }
-        }
-        Ok(())
-    }
-}
-
-impl WorldState for Blockchain {
-    fn get_user_ids(&self) -> Vec<String> {
-        self.accounts.keys().map(|s| s.clone()).collect()
-    }
-
-    fn get_account_by_id_mut(&mut self, id: &String) -> Option<&mut Account> {
-        self.accounts.get_mut(id)
-    }
-
-    fn get_account_by_id(&self, id: &String) -> Option<&Account> {
-        self.accounts.get(id)
-    }
-
-    fn create_account(&mut self, id: String,
-                      account_type: AccountType) -> Result<(), &'static str> {
-        return if !self.get_user_ids().contains(&id) {
-            let acc = Account::new(account_type);
-            self.accounts.insert(id, acc);
-            Ok(())
-        } else {
-            Err("User already exists! (Code: 934823094)")
-        };
-    }
-}
-
-impl Block {
-    pub fn new(prev_hash: Option<String>) -> Self {
-        Block {
-            nonce: 0,
-            hash: None,
-            prev_hash,
-            transactions: Vec::new(),
-        }
-    }
-
-    /// Changes the nonce number and updates the hash
-    pub fn set_nonce(&mut self, nonce: u128) {
-        self.nonce = nonce;
-        self.update_hash();
-    }
-
-    /// Will calculate the hash of the whole block including transactions Blake2 hasher
-    pub fn calculate_hash(&self) -> Vec<u8> {
-        let mut hasher = Blake2b::new();
-
-        for transaction in self.transactions.iter() {
-            hasher.update(transaction.calculate_hash())
-        }
-
-        let block_as_string = format!("{:?}", (&self.prev_hash, &self.nonce));
-        hasher.update(&block_as_string);
-
-        return Vec::from(hasher.finalize().as_ref());
-    }
-
-    /// Appends a transaction to the queue
-    pub fn add_transaction(&mut self, transaction: Transaction) {
-        self.transactions.push(transaction);
-        self.update_hash();
-    }
-
-    /// Will return the amount of transactions
-    pub fn get_transaction_count(&self) -> usize {
-        self.transactions.len()
-    }
-
-    /// Will update the hash field by including all transactions currently inside
-    /// the public modifier is only for the demonstration of attacks
-    pub(crate) fn update_hash(&mut self) {
-        self.hash = Some(byte_vector_to_string(&self.calculate_hash()));
-    }
-
-    /// Checks if the hash is set and matches the blocks interna
-    pub fn verify_own_hash(&self) -> bool {
-        if self.hash.is_some() && // Hash set
-            self.hash.as_ref().unwrap().eq(
-                &byte_vector_to_string(
-                    &self.calculate_hash())) { // Hash equals calculated hash
-
-            return true;
-        }
-        false
-    }
-}
-
-impl Transaction {
-    pub fn new(from: String, transaction_data: TransactionData, nonce: u128) -> Self {
-        Transaction {
-            from,
-            nonce,
-            record: transaction_data,
-            created_at: SystemTime::now(),
-            signature: None,
-        }
-    }
-
-    /// Will change the world state according to the transactions commands
-    pub fn execute<T: WorldState>(&self, world_state: &mut T, is_initial: &bool) -> Result<(), &'static str> {
-        // Check if sending user does exist (no one not on the chain can execute transactions)
-        if let Some(_account) = world_state.get_account_by_id(&self.from) {
-            // Do some more checkups later on...
-        } else {
-            if !is_initial {
-                return Err("Account does not exist (Code: 93482390)");
-            }
-        }
-
-        // match is like a switch (pattern matching) in C++ or Java
-        // We will check for the type of transaction here and execute its logic
-        return match &self.record {
-
-            TransactionData::CreateUserAccount(account) => {
-                world_state.create_account(account.into(), AccountType::User)
-            }
-
-            TransactionData::CreateTokens { receiver, amount } => {
-                if !is_initial {
-                    return Err("Token creation is only available on initial creation (Code: 2394233)");
-                }
-                // Get the receiving user (must exist)
-                return if let Some(account) = world_state.get_account_by_id_mut(receiver) {
-                    account.tokens += *amount;
-                    Ok(())
-                } else {
-                    Err("Receiver Account does not exist (Code: 23482309)")
-                };
-            }
-
-            TransactionData::TransferTokens { to, amount } => {
-                let recv_tokens: u128;
-                let sender_tokens: u128;
-
-                if let Some(recv) = world_state.get_account_by_id_mut(to) {
-                    // Be extra careful here, even in the genesis block the sender account has to exist
-                    recv_tokens = recv.tokens;
-                } else {
-                    return Err("Receiver Account does not exist! (Code: 3242342380)");
-                }
-
-                if let Some(sender) = world_state.get_account_by_id_mut(&self.from) {
-                    sender_tokens = sender.tokens;
-                } else {
-                    return Err("That account does not exist! (Code: 23423923)");
-                }
-
-                let balance_recv_new = recv_tokens.checked_add(*amount);
-                let balance_sender_new = sender_tokens.checked_sub(*amount);
-
-                if balance_recv_new.is_some() && balance_sender_new.is_some() {
-                    world_state.get_account_by_id_mut(&self.from).unwrap().tokens = balance_sender_new.unwrap();
-                    world_state.get_account_by_id_mut(to).unwrap().tokens = balance_recv_new.unwrap();
-                    return Ok(());
-                } else {
-                    return Err("Overspent or Arithmetic error (Code: 48239084203)");
-                }
-            }
-
-            _ => { // Not implemented transaction type
-                Err("Unknown Transaction type (not implemented) (Code: 487289724389)")
-            }
-        };
-    }
-
-    /// Will calculate the hash using Blake2 hasher
-    pub fn calculate_hash(&self) -> Vec<u8> {
-        let mut hasher = Blake2b::new();
-        let transaction_as_string = format!("{:?}", (&self.created_at, &self.record,
-                                                     &self.from, &self.nonce));
-
-        hasher.update(&transaction_as_string);
-        return Vec::from(hasher.finalize().as_ref());
-    }
-
-    /// Will hash the transaction and check if the signature is valid
-    /// (i.e., it is created by the owners private key)
-    /// if the message is not signed it will always return false
-    pub fn check_signature(&self) -> bool {
-        if !(self.is_signed()) {
-            return false;
-        }
-
-        //@TODO check signature
-        false
-    }
-
-    pub fn is_signed(&self) -> bool {
-        self.signature.is_some()
-    }
-}
-
-impl Account {
-    /// Constructor
-    pub fn new(account_type: AccountType) -> Self {
-        return Self {
-            tokens: 0,
-            acc_type: account_type,
-            store: HashMap::new(),
-        };
-    }
-}
-
-/// Will take an array of bytes and transform it into a string by interpreting every byte
-/// as an character due to RFC 1023 that's not possible
-/// @Link https://github.com/rust-lang/rfcs/blob/master/text/1023-rebalancing-coherence.md
-/// (trait and parameters are not within the local crate)
-/*impl From<&std::vec::Vec<u8>> for std::string::String {
-    fn from(item: &Vec<u8>) -> Self {
-        item.iter().map(|&c| c as char).collect()
-    }
-}*/
-
-/// Will take an array of bytes and transform it into a string by interpreting every byte
-/// as an character
-fn byte_vector_to_string(arr: &Vec<u8>) -> String {
-    arr.iter().map(|&c| c as char).collect()
-}
+extern crate blake2;
+
+mod audit;
+mod authority;
+mod contract;
+pub mod encoding;
+mod error;
+#[cfg(feature = "serde")]
+mod export;
+mod fork;
+mod gas;
+mod hash;
+mod journal;
+mod keys;
+mod light_client;
+mod mempool;
+mod merkle;
+mod node;
+mod rpc;
+mod spec;
+mod storage;
+
+pub use audit::{AuditReport, Finding, Severity, StateReport, AccountDifference};
+pub use contract::{Instruction, ContractError, encode_code, decode_code, MAX_CODE_LENGTH, MAX_STACK_DEPTH};
+pub use error::{ChainError, TransactionError};
+pub use fork::{BlockStatus, ReceiveOutcome, block_work, MAX_ORPHANS, MAX_FORK_DEPTH, MAX_SIDE_BLOCKS};
+pub use gas::{Receipt, DEFAULT_GAS_LIMIT, DEFAULT_BLOCK_GAS_LIMIT};
+pub use hash::{Hash, HASH_LENGTH};
+pub use journal::{Journal, JournaledState};
+pub use keys::{Keypair, verify_signature};
+pub use light_client::{LightClient, InclusionProof};
+pub use merkle::{MerkleProof, merkle_root, merkle_proof};
+pub use node::Node;
+pub use rpc::{RpcBackend, RpcServer, handle_request};
+pub use spec::{ChainSpec, GenesisAccount};
+pub use storage::BlockStore;
+
+use std::vec::Vec;
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::convert::Into;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::string::String;
+
+
+/// The actual Blockchain container
+/// With the `serde` feature it can be serialized. Reading it back appends every block again,
+/// since the data is not trusted (see `import_json`)
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Blockchain {
+    /// Stores all the blocks which are accepted already within the blockchain
+    pub blocks: Vec<Block>,
+
+    /// Lookup from AccountID (will be a public key later) to Account.
+    /// Effectively, this represents the WorldState
+    #[cfg_attr(feature = "serde", serde(serialize_with = "export::sorted"))]
+    pub accounts: HashMap<String, Account>,
+
+    /// Will store transactions which should be added to the chain
+    /// but aren't yet (see `submit_transaction`)
+    pending_transactions: Vec<Transaction>,
+
+    /// The accounts after executing all pending transactions, so a submitted transaction
+    /// is only executed on top of it. Built on demand and dropped whenever the accounts or
+    /// the pending transactions change otherwise (see `submit_transaction`)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pending_state: Option<HashMap<String, Account>>,
+
+    /// If set, every transaction has to be signed by the sending account
+    /// (i.e., the AccountID has to be the hex encoded public key of the signer)
+    require_signatures: bool,
+
+    /// Rules for the Proof of Work (difficulty and its adjustment)
+    params: ChainParams,
+
+    /// If set, every accepted block is also written to disk (see `open_store`)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    store: Option<BlockStore>,
+
+    /// The changes every block made to the accounts (same order as `blocks`)
+    /// Allows to undo blocks without keeping copies of the whole world state
+    #[cfg_attr(feature = "serde", serde(skip))]
+    journals: Vec<Journal>,
+
+    /// The receipts of the transactions of every block (same order as `blocks`)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    receipts: Vec<Vec<Receipt>>,
+
+    /// Valid looking blocks which are not part of the main chain (see `receive_block`)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    side_blocks: HashMap<Hash, Block>,
+
+    /// Blocks whose parent is not known yet, oldest first (see `receive_block`)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    orphans: VecDeque<Block>,
+
+    /// If set, only the genesis block with that hash is accepted (see `with_spec`)
+    genesis_hash: Option<Hash>,
+}
+
+/// Parameters that define how much work has to be done for a block (and what it pays)
+/// The difficulty is the amount of leading zero bits every block hash must have.
+/// Every `retarget_interval` blocks the difficulty is recomputed (Bitcoin-style) by comparing
+/// the time the last blocks actually took against the `target_block_time`.
+/// The block reward starts at `initial_reward` and halves every `halving_interval` blocks
+/// The transactions of a block may use at most `block_gas_limit` gas (see `gas`)
+/// With `proof_of_authority` set, the validators take turns producing blocks (see `authority`)
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChainParams {
+    /// Difficulty of the genesis block (and all blocks until the first retarget)
+    /// 0 means that no work is required at all
+    pub initial_difficulty: u32,
+
+    /// Amount of blocks after which the difficulty is recomputed (0 disables retargeting)
+    pub retarget_interval: usize,
+
+    /// The time in seconds we would like to pass between two blocks
+    pub target_block_time: u64,
+
+    /// The amount of tokens the coinbase of a block may create (until the first halving)
+    pub initial_reward: u128,
+
+    /// Amount of blocks after which the reward is cut in half (0 keeps it forever)
+    pub halving_interval: usize,
+
+    /// The most gas the transactions of a single block may use
+    pub block_gas_limit: u64,
+
+    /// Only the validator whose turn it is may produce (and has to sign) a block
+    pub proof_of_authority: bool,
+}
+
+/// Everything a transaction needs to know about where it is executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionContext {
+    /// Set for the transactions of the genesis block (which may create users and tokens)
+    pub is_initial: bool,
+
+    /// Position within the block (None if the transaction is not part of a block, e.g. pending)
+    pub position: Option<usize>,
+
+    /// The amount of tokens a coinbase may create
+    pub reward: u128,
+
+    /// Receives the fees of the transactions (the receiver of the coinbase)
+    /// If there is none, the fees are burned
+    pub producer: Option<String>,
+}
+
+impl ExecutionContext {
+    /// The context of a transaction which is not part of a block yet
+    /// (a coinbase is rejected, fees are burned)
+    pub fn pending(is_initial: bool) -> Self {
+        ExecutionContext {
+            is_initial,
+            position: None,
+            reward: 0,
+            producer: None,
+        }
+    }
+}
+
+/// Represents the current state of the blockchain after all Blocks are executed
+/// A world state is technically not necessary since we always could build the information
+/// by iterating through all the blocks. Generally, this doesn't seem like a good option
+/// However, we do not force the actual Blockchain to implement a WorldState but rather
+/// behave like having one. This trait therefore just defines an expected interface into our Blockchain
+/// (Actually it doesn't even care if we the information is stored within a blockchain)
+pub trait WorldState {
+    /// Will bring us all registered user ids
+    fn get_user_ids(&self) -> Vec<String>;
+
+    /// Will return an account given it id if is available (mutable)
+    fn get_account_by_id_mut(&mut self, id: &str) -> Option<&mut Account>;
+
+    /// Will return an account given it id if is available
+    fn get_account_by_id(&self, id: &str) -> Option<&Account>;
+
+    /// Will add a new account
+    fn create_account(&mut self, id: String, account_type: AccountType) -> Result<(), TransactionError>;
+
+    /// Will return a value of the key-value store of an account (if account and key exist)
+    fn get_store_value(&self, id: &str, key: &str) -> Option<&String> {
+        self.get_account_by_id(id)?.get_store_value(key)
+    }
+
+    /// Will return the amount of tokens an account owns (if the account exists)
+    fn get_balance(&self, id: &str) -> Option<u128> {
+        Some(self.get_account_by_id(id)?.get_tokens())
+    }
+}
+
+/// Maximum length of a key within the store of an account (in bytes)
+pub const MAX_STORE_KEY_LENGTH: usize = 64;
+
+/// Maximum length of a value within the store of an account (in bytes)
+pub const MAX_STORE_VALUE_LENGTH: usize = 1024;
+
+/// Maximum amount of keys a single account may store
+pub const MAX_STORE_ENTRIES: usize = 128;
+
+/// How many seconds the timestamp of a block may be ahead of the local clock
+/// (otherwise a producer could make the blocks look slow and lower the difficulty)
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+/// One single part of the blockchain.
+/// Basically contains a list of transactions
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    /// Everything that is covered by the block hash
+    header: BlockHeader,
+
+    /// Actions that this block includes
+    /// There has to be at least one
+    /// (the public modifier is only for the demonstration of attacks)
+    pub transactions: Vec<Transaction>,
+
+    /// We store the hash of the block here also in order to
+    /// save the last block from being tampered with later on
+    hash: Option<Hash>,
+
+    /// Signature of the block hash by the validator named in the header (hex encoded)
+    signature: Option<String>,
+}
+
+/// The part of a block which is hashed
+/// The transactions are only included by their Merkle root, so the header
+/// is enough to check the chain (see `LightClient`)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockHeader {
+    /// This actually connects the blocks together
+    prev_hash: Option<Hash>,
+
+    /// Root of the Merkle tree over all transaction hashes
+    /// Allows to prove that a transaction is part of the block without the whole block
+    tx_root: Hash,
+
+    /// Some arbitrary number which is altered in order to find a hash
+    /// that satisfies the Proof of Work
+    nonce: u128,
+
+    /// Seconds since UNIX epoch when the block was created
+    timestamp: u64,
+
+    /// The difficulty the block was mined with (has to match the chains' rules for its height)
+    difficulty: u32,
+
+    /// The validator producing the block (only used with Proof of Authority, see `authority`)
+    validator: Option<String>,
+}
+
+/// Stores a request to the blockchain
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transaction {
+    /// Sequence number of the sending account (prevents replay attacks)
+    /// Has to match the accounts' next nonce (0 for the first transaction after its creation)
+    nonce: u128,
+
+    /// Tokens the sender pays to the producer of the block (0 if none)
+    fee: u128,
+
+    /// The most gas the transaction may use (see `gas`)
+    gas_limit: u64,
+
+    /// Tokens the sender pays for every unit of gas used
+    gas_price: u128,
+
+    /// Account ID
+    from: String,
+
+    /// Stores the time the transaction was created
+    created_at: SystemTime,
+
+    /// the type of the transaction and its additional information
+    /// (the public modifier is only for the demonstration of attacks)
+    pub record: TransactionData,
+
+    /// Signature of the hash of the whole message (hex encoded)
+    signature: Option<String>,
+}
+
+/// A single operation to be stored on the chain
+/// Noticeable, enums in rust actually can carry data in a
+/// tuple-like structure (CreateUserAccount) or a dictionary-like (the ChangeStoreValue)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransactionData {
+    /// Will be used to store a new user account
+    CreateUserAccount(String),
+
+    /// Will be used to change or create a arbitrary value into the store of the sending account
+    /// (nobody can write into the store of others). An empty value deletes the key
+    /// See `MAX_STORE_KEY_LENGTH`, `MAX_STORE_VALUE_LENGTH` and `MAX_STORE_ENTRIES` for the limits
+    ChangeStoreValue { key: String, value: String },
+
+    /// Will be used to move tokens from one owner to another
+    TransferTokens { to: String, amount: u128 },
+
+    /// Just create tokens out of nowhere
+    CreateTokens { receiver: String, amount: u128 },
+
+    /// Will create an account of any type (only allowed within the genesis block)
+    CreateAccount { account: String, account_type: AccountType },
+
+    /// Records the id and the rules of the chain (only allowed within the genesis block)
+    /// so chains with another spec have another genesis block (see `ChainSpec`)
+    ChainInfo { chain_id: String, params: ChainParams, require_signatures: bool },
+
+    /// Pays the block reward to the producer of the block
+    /// Has to be the first transaction of a block (so there is at most one per block)
+    /// and may not pay more than the reward at the blocks' height (see `ChainParams::reward_at`)
+    Coinbase { receiver: String, amount: u128 },
+
+    /// Will create a contract account holding the bytecode (see `contract`)
+    DeployContract { account: String, code: Vec<u8> },
+
+    /// Will run the code of a contract, sending `amount` tokens along
+    /// The arguments can be read by the contract (see `Instruction::Arg`)
+    CallContract { contract: String, amount: u128, args: Vec<String> },
+
+    // ... Extend it as you wish, you get the idea
+}
+
+/// Represents an account on the blockchain
+/// This is basically the primary part of the "world state" of the blockchain
+/// It is the final status after performing all blocks in order
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Account {
+    /// We want the account to be able to store any information we want (Dictionary)
+    #[cfg_attr(feature = "serde", serde(serialize_with = "export::sorted"))]
+    store: HashMap<String, String>,
+
+    /// store if this is a user account or sth else
+    acc_type: AccountType,
+
+    /// Amount of tokens that account owns (like BTC or ETH)
+    tokens: u128,
+
+    /// The nonce the next transaction sent by this account has to use
+    /// (Every nonce can only be used once and in order. This prevents replay attacks)
+    nonce: u128,
+
+    /// Bytecode run on every call (only used by contracts, see `contract`)
+    code: Vec<u8>,
+}
+
+/// We can support different types of accounts
+/// which could be used to represent different roles within the system
+/// Validators are only used by chains with Proof of Authority
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccountType {
+    /// A common user account
+    User,
+
+    /// An account that technically does not represent an individual
+    /// Think of this like a SmartContract in Ethereum: It holds bytecode which is run
+    /// whenever somebody calls it (see `TransactionData::DeployContract`)
+    Contract,
+
+    /// May produce blocks on chains with Proof of Authority (see `authority`)
+    /// The account id has to be the validators' public key, since it signs its blocks
+    Validator {
+        // Again, enum members in rust may store additional data
+        /// Blocks of the validator accepted by the chain
+        correctly_validated_blocks: u128,
+
+        /// Blocks of the validator rejected by this node, while it was the validator's turn
+        /// (no block records them, so they are not replayed: the count starts at zero
+        /// whenever the chain is loaded)
+        incorrectly_validated_blocks: u128,
+        you_get_the_idea: bool,
+    },
+}
+
+impl Blockchain {
+    /// Constructor
+    pub fn new() -> Self {
+        Blockchain {
+            blocks: Vec::new(),
+            accounts: HashMap::new(),
+            pending_transactions: Vec::new(),
+            pending_state: None,
+            require_signatures: false,
+            params: ChainParams::default(),
+            store: None,
+            journals: Vec::new(),
+            receipts: Vec::new(),
+            side_blocks: HashMap::new(),
+            orphans: VecDeque::new(),
+            genesis_hash: None,
+        }
+    }
+
+    /// Will make the chain persistent: All blocks stored within the given file are loaded
+    /// and every block appended afterwards is written into that file
+    /// The stored blocks are replayed through `append_block`, so they have to be valid
+    /// according to the chains' current settings. Only allowed on an empty chain
+    pub fn open_store<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), ChainError> {
+        if !self.is_empty() || self.store.is_some() {
+            return Err(ChainError::StoreNotEmpty);
+        }
+
+        let (store, blocks) = BlockStore::open(path)?;
+        for (block_num, block) in blocks.into_iter().enumerate() {
+            if let Err(err) = self.append_block(block) {
+                // Don't leave a half loaded chain behind
+                self.blocks.clear();
+                self.accounts.clear();
+                self.journals.clear();
+                self.receipts.clear();
+                return Err(ChainError::StoredBlockRejected { block: block_num, error: Box::new(err) });
+            }
+        }
+
+        self.store = Some(store);
+        Ok(())
+    }
+
+    /// Will return the store the chain is persisted to (if any)
+    pub fn get_store(&self) -> Option<&BlockStore> {
+        self.store.as_ref()
+    }
+
+    /// Will set the rules for the Proof of Work
+    /// Should be set before any block is added, otherwise the existing blocks may become invalid
+    pub fn set_params(&mut self, params: ChainParams) {
+        self.params = params;
+    }
+
+    /// Will return the rules for the Proof of Work
+    pub fn get_params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    /// Will set the amount of leading zero bits the blocks must have (until the first retarget)
+    /// Should be set before any block is added, otherwise the existing blocks may become invalid
+    pub fn set_difficulty(&mut self, difficulty: u32) {
+        self.params.initial_difficulty = difficulty;
+    }
+
+    /// Will return the difficulty the next block has to be mined with
+    pub fn get_difficulty(&self) -> u32 {
+        self.params.difficulty_at(&self.blocks, self.len())
+    }
+
+    /// Will make the chain only accept blocks which contain correctly signed transactions
+    pub fn set_require_signatures(&mut self, require_signatures: bool) {
+        self.require_signatures = require_signatures;
+    }
+
+    /// Will tell if the chain only accepts correctly signed transactions
+    pub fn requires_signatures(&self) -> bool {
+        self.require_signatures
+    }
+
+    /// Will tell if the transactions of the block at `height` have to be signed
+    /// A genesis block built from a spec is trusted as it is (its transactions have no sender)
+    pub fn requires_signatures_at(&self, height: usize) -> bool {
+        self.require_signatures && !(height == 0 && self.genesis_hash.is_some())
+    }
+
+    /// Will return the hash of the only genesis block accepted (if set by a spec)
+    pub fn get_genesis_hash(&self) -> Option<Hash> {
+        self.genesis_hash
+    }
+
+    /// Will add a block to the Blockchain
+    /// @TODO every simple step could be refactored into a separate function for
+    /// better testability and code-reusability
+    pub fn append_block(&mut self, block: Block) -> Result<(), ChainError> {
+        let height = self.len();
+
+        // A block signed by the validator in charge, but rejected anyway, counts against it
+        // (unless we just failed to write it)
+        let signer = if self.params.proof_of_authority && height > 0
+            && block.header.prev_hash == self.get_last_block_hash() {
+            authority::scheduled_signer(&block, &self.accounts, height)
+        } else {
+            None
+        };
+
+        let result = self.insert_block(block);
+        if let (Err(err), Some(validator)) = (&result, signer) {
+            if !matches!(err, ChainError::StoreIo { .. }) {
+                authority::blame_validator(&validator, &mut self.accounts);
+            }
+        }
+
+        result
+    }
+
+    fn insert_block(&mut self, block: Block) -> Result<(), ChainError> {
+
+        // The genesis block (height 0) may create user out of nowhere,
+        // and also may do some other things (see `ExecutionContext`)
+        let height = self.len();
+
+        // Check if the hash matches the transactions
+        if !block.verify_own_hash() {
+            return Err(ChainError::HashMismatch { block: height });
+        }
+
+        // Only the genesis block of the spec is accepted (if there is one)
+        if let (0, Some(expected)) = (height, self.genesis_hash) {
+            if block.calculate_hash() != expected {
+                return Err(ChainError::GenesisMismatch { expected, actual: block.calculate_hash() });
+            }
+        }
+
+        // Check if the block was mined with the difficulty that applies at its height
+        let difficulty = self.get_difficulty();
+        if block.header.difficulty != difficulty {
+            return Err(ChainError::DifficultyMismatch {
+                block: height,
+                expected: difficulty,
+                actual: block.header.difficulty,
+            });
+        }
+
+        // Check if somebody did the actual work to create this block
+        if !block.meets_difficulty(difficulty) {
+            return Err(ChainError::InsufficientWork { block: height, difficulty });
+        }
+
+        // Time may not run backwards
+        if let Some(last_block) = self.blocks.last() {
+            if block.header.timestamp < last_block.header.timestamp {
+                return Err(ChainError::TimestampBeforeParent { block: height });
+            }
+        }
+
+        // Nor may it run far ahead of our clock
+        if block.header.timestamp > unix_timestamp().saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(ChainError::TimestampTooFarAhead { block: height });
+        }
+
+        // Check if the newly added block is meant to be appended onto the last block
+        if block.header.prev_hash != self.get_last_block_hash() {
+            return Err(ChainError::BrokenLinkage {
+                block: height,
+                expected: self.get_last_block_hash(),
+                actual: block.header.prev_hash,
+            });
+        }
+
+        // There has to be at least one transaction inside the queue
+        if block.get_transaction_count() == 0 {
+            return Err(ChainError::EmptyBlock { block: height });
+        }
+
+        // If the chain demands it, every single transaction has to be signed by its sender
+        // (except the coinbase, which has no sender and is bounded by the block reward)
+        if self.requires_signatures_at(height) {
+            for (i, transaction) in block.transactions.iter().enumerate() {
+                if !transaction.is_coinbase() && !transaction.check_signature() {
+                    return Err(ChainError::InvalidSignature {
+                        block: height,
+                        transaction: i,
+                        account: transaction.from.clone(),
+                    });
+                }
+            }
+        }
+
+        // Blocks having nonces that are already used or out of order are rejected
+        // while executing the transactions (Prevent reply attacks etc.)
+
+        // Every change is recorded, so we can roll back if some transactions succeed whilst
+        // others don't (prevent inconsistent states)
+        let mut journal = Journal::new();
+        let result = block.execute(&mut JournaledState::new(&mut self.accounts, &mut journal),
+                                   &self.params, height);
+        let receipts = match result {
+            Ok(receipts) => receipts,
+            Err(err) => {
+                // Recover state on failure and reject the block
+                journal.undo(&mut self.accounts);
+                return Err(err);
+            }
+        };
+
+        // Persist the block before accepting it, so memory and disk don't diverge
+        if let Some(store) = &self.store {
+            if let Err(err) = store.append(&block) {
+                journal.undo(&mut self.accounts);
+                return Err(err);
+            }
+        }
+
+        // Everything went fine... append the block
+        self.blocks.push(block);
+        self.journals.push(journal);
+        self.receipts.push(receipts);
+
+        // The included transactions are not pending anymore
+        self.prune_pending_transactions();
+
+        Ok(())
+    }
+
+    /// Will remove all blocks after the block at `height` (0 is the genesis block, which stays)
+    /// and reverse their changes to the accounts. Their transactions go back into the pending
+    /// transactions if they are still valid. Returns the removed blocks (oldest first)
+    /// Side branches built on top of the removed blocks are dropped as well
+    pub fn rewind_to(&mut self, height: usize) -> Result<Vec<Block>, ChainError> {
+        let removed = self.rewind(height)?;
+        for block in removed.iter() {
+            self.drop_side_descendants(&block.calculate_hash());
+        }
+
+        Ok(removed)
+    }
+
+    /// Like `rewind_to`, but keeps the side branches (a reorganization reconnects them)
+    fn rewind(&mut self, height: usize) -> Result<Vec<Block>, ChainError> {
+        if height >= self.len() {
+            return Err(ChainError::UnknownHeight { height });
+        }
+
+        // Disk first, so memory and disk don't diverge if that fails
+        if let Some(store) = &self.store {
+            store.truncate(height + 1)?;
+        }
+
+        let removed = self.blocks.split_off(height + 1);
+        // (Blocks pushed onto `blocks` directly have no journal)
+        let journals = self.journals.split_off((height + 1).min(self.journals.len()));
+        self.receipts.truncate(height + 1);
+        for journal in journals.into_iter().rev() {
+            journal.undo(&mut self.accounts);
+        }
+
+        // The transactions of the removed blocks come first, since they were accepted earlier
+        let pending = std::mem::take(&mut self.pending_transactions);
+        self.pending_state = None;
+        let transactions = removed.iter()
+            .flat_map(|block| block.transactions.iter().cloned())
+            .chain(pending);
+        for transaction in transactions {
+            // Transactions which aren't valid anymore are just dropped
+            let _ = self.submit_transaction(transaction);
+        }
+
+        Ok(removed)
+    }
+
+    /// Will return the receipts of the transactions of the block at `height` (same order)
+    /// None if there is no such block (or it was not appended by `append_block`)
+    pub fn get_receipts(&self, height: usize) -> Option<&[Receipt]> {
+        self.receipts.get(height).map(Vec::as_slice)
+    }
+
+    /// Will return the amount of blocks currently stored
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Will tell if there are no blocks stored at all (not even the genesis block)
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Will return the hash of the last block
+    pub fn get_last_block_hash(&self) -> Option<Hash> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.blocks[self.len() - 1].hash
+    }
+
+    /// Checks if the blockchain was tempered with
+    /// It will return the first error found (see `audit` for all of them)
+    /// if everything is fine it will return Ok
+    pub fn check_validity(&self) -> Result<(), ChainError> {
+        return match self.audit().errors().next() {
+            Some(finding) => Err(finding.error.clone()),
+            None => Ok(()),
+        };
+    }
+}
+
+impl ChainParams {
+    /// Will return the amount of tokens the coinbase of the block at `height` may create
+    pub fn reward_at(&self, height: usize) -> u128 {
+        if self.halving_interval == 0 {
+            return self.initial_reward;
+        }
+
+        let halvings = (height / self.halving_interval) as u32;
+        self.initial_reward.checked_shr(halvings).unwrap_or(0)
+    }
+
+    /// Will calculate the difficulty a block at `height` has to use, given all blocks before it
+    /// (`blocks` has to contain at least `height` blocks, additional ones are ignored)
+    /// Works on full blocks as well as on headers only
+    pub fn difficulty_at<T: AsRef<BlockHeader>>(&self, blocks: &[T], height: usize) -> u32 {
+        // The first block just uses the initial difficulty
+        if height == 0 {
+            return self.initial_difficulty;
+        }
+
+        let previous_difficulty = blocks[height - 1].as_ref().difficulty;
+
+        // Only adjust every `retarget_interval` blocks
+        if self.retarget_interval == 0 || !height.is_multiple_of(self.retarget_interval) {
+            return previous_difficulty;
+        }
+
+        // Measure the time the last `retarget_interval` blocks took
+        // (the very first window has to do without the block before it)
+        let first = height.saturating_sub(self.retarget_interval + 1);
+        let gaps = (height - 1 - first) as u64;
+        let actual = blocks[height - 1].as_ref().timestamp
+            .saturating_sub(blocks[first].as_ref().timestamp);
+        let expected = self.target_block_time * gaps;
+
+        if expected == 0 {
+            return previous_difficulty;
+        }
+
+        // Every bit doubles the work, so we adjust by the (rounded) binary logarithm of
+        // expected / actual. Like Bitcoin we never change by more than a factor of 4 at once
+        // Integers only (factors in thousandths), so every node calculates exactly the same
+        let actual = actual as u128 * 1000;
+        let expected = expected as u128;
+        if actual <= expected * 354 {
+            previous_difficulty.saturating_add(2) // more than 2^1.5 times too fast
+        } else if actual <= expected * 707 {
+            previous_difficulty.saturating_add(1) // more than 2^0.5 times too fast
+        } else if actual >= expected * 2828 {
+            previous_difficulty.saturating_sub(2) // more than 2^1.5 times too slow
+        } else if actual >= expected * 1414 {
+            previous_difficulty.saturating_sub(1) // more than 2^0.5 times too slow
+        } else {
+            previous_difficulty
+        }
+    }
+
+    /// Checks if the header of the block at `height` is connected to the block before it,
+    /// uses the right difficulty and if the work was actually done
+    /// (`blocks` has to contain at least `height + 1` blocks, additional ones are ignored)
+    /// Works on full blocks as well as on headers only
+    pub fn check_header<T: AsRef<BlockHeader>>(&self, blocks: &[T], height: usize) -> Result<(), ChainError> {
+        match self.header_errors(blocks, height).into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `check_header`, but returns every problem of the header instead of just the first one
+    pub fn header_errors<T: AsRef<BlockHeader>>(&self, blocks: &[T], height: usize) -> Vec<ChainError> {
+        let header = blocks[height].as_ref();
+        let mut errors = Vec::new();
+
+        // Check if the block used the difficulty that applied at its height
+        let difficulty = self.difficulty_at(blocks, height);
+        if header.difficulty != difficulty {
+            errors.push(ChainError::DifficultyMismatch {
+                block: height,
+                expected: difficulty,
+                actual: header.difficulty,
+            });
+        }
+
+        // Check if the work was done for that block
+        if header.calculate_hash().leading_zero_bits() < difficulty {
+            errors.push(ChainError::InsufficientWork { block: height, difficulty });
+        }
+
+        // Blocks from the future would lower the difficulty of the blocks after them
+        if header.timestamp > unix_timestamp().saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            errors.push(ChainError::TimestampTooFarAhead { block: height });
+        }
+
+        // Check previous black hash points to actual previous block
+        if height == 0 {
+            // Genesis block should point to nowhere
+            if header.prev_hash.is_some() {
+                errors.push(ChainError::GenesisHasParent);
+            }
+
+            return errors;
+        }
+
+        let previous = blocks[height - 1].as_ref();
+
+        // Time may not run backwards
+        if header.timestamp < previous.timestamp {
+            errors.push(ChainError::TimestampBeforeParent { block: height });
+        }
+
+        // Non genesis blocks should point to previous blocks hash
+        let prev_hash_actual = previous.calculate_hash();
+        match header.prev_hash {
+            None => errors.push(ChainError::MissingPrevHash { block: height }),
+            Some(prev_hash_proposed) if prev_hash_proposed != prev_hash_actual => {
+                errors.push(ChainError::BrokenLinkage {
+                    block: height,
+                    expected: Some(prev_hash_actual),
+                    actual: Some(prev_hash_proposed),
+                });
+            }
+            Some(_) => {}
+        }
+
+        errors
+    }
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        ChainParams {
+            initial_difficulty: 0,
+            retarget_interval: 0,
+            target_block_time: 60,
+            initial_reward: 0,
+            halving_interval: 0,
+            block_gas_limit: DEFAULT_BLOCK_GAS_LIMIT,
+            proof_of_authority: false,
+        }
+    }
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorldState for Blockchain {
+    fn get_user_ids(&self) -> Vec<String> {
+        self.accounts.keys().cloned().collect()
+    }
+
+    fn get_account_by_id_mut(&mut self, id: &str) -> Option<&mut Account> {
+        self.pending_state = None;
+        self.accounts.get_mut(id)
+    }
+
+    fn get_account_by_id(&self, id: &str) -> Option<&Account> {
+        self.accounts.get(id)
+    }
+
+    #[allow(clippy::needless_return)]
+    fn create_account(&mut self, id: String,
+                      account_type: AccountType) -> Result<(), TransactionError> {
+        self.pending_state = None;
+        return if !self.get_user_ids().contains(&id) {
+            let acc = Account::new(account_type);
+            self.accounts.insert(id, acc);
+            Ok(())
+        } else {
+            Err(TransactionError::AccountExists { account: id })
+        };
+    }
+}
+
+/// Allows to use a plain account lookup as a world state
+/// (e.g., a scratch copy of the accounts for trying out transactions)
+impl WorldState for HashMap<String, Account> {
+    fn get_user_ids(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
+
+    fn get_account_by_id_mut(&mut self, id: &str) -> Option<&mut Account> {
+        self.get_mut(id)
+    }
+
+    fn get_account_by_id(&self, id: &str) -> Option<&Account> {
+        self.get(id)
+    }
+
+    fn create_account(&mut self, id: String,
+                      account_type: AccountType) -> Result<(), TransactionError> {
+        match self.entry(id) {
+            Entry::Vacant(entry) => {
+                entry.insert(Account::new(account_type));
+                Ok(())
+            }
+            Entry::Occupied(entry) => Err(TransactionError::AccountExists { account: entry.key().clone() }),
+        }
+    }
+}
+
+impl Block {
+    pub fn new(prev_hash: Option<Hash>) -> Self {
+        Block {
+            header: BlockHeader {
+                prev_hash,
+                tx_root: merkle_root(&[]),
+                nonce: 0,
+                timestamp: unix_timestamp(),
+                difficulty: 0,
+                validator: None,
+            },
+            hash: None,
+            transactions: Vec::new(),
+            signature: None,
+        }
+    }
+
+    /// Changes the nonce number and updates the hash
+    pub fn set_nonce(&mut self, nonce: u128) {
+        self.header.nonce = nonce;
+        self.update_hash();
+    }
+
+    /// Will try nonce after nonce until the hash of the block starts with
+    /// (at least) `difficulty` zero bits. This is the actual "work" in Proof of Work:
+    /// Every additional bit doubles the expected amount of tries
+    /// Has to be called after all transactions are added
+    pub fn mine(&mut self, difficulty: u32) {
+        self.header.tx_root = self.calculate_tx_root();
+        self.header.difficulty = difficulty;
+        self.header.nonce = 0;
+        while self.header.calculate_hash().leading_zero_bits() < difficulty {
+            self.header.nonce += 1;
+        }
+
+        self.update_hash();
+    }
+
+    /// Changes the creation time (seconds since UNIX epoch) and updates the hash
+    /// (Has to be done before mining)
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.header.timestamp = timestamp;
+        self.update_hash();
+    }
+
+    /// Returns the creation time (seconds since UNIX epoch)
+    pub fn get_timestamp(&self) -> u64 {
+        self.header.timestamp
+    }
+
+    /// Returns the difficulty the block was mined with
+    pub fn get_difficulty(&self) -> u32 {
+        self.header.difficulty
+    }
+
+    /// Returns the header of the block (everything that is covered by its hash)
+    pub fn get_header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// Checks if the hash of the block starts with (at least) `difficulty` zero bits
+    pub fn meets_difficulty(&self, difficulty: u32) -> bool {
+        self.calculate_hash().leading_zero_bits() >= difficulty
+    }
+
+    /// Will calculate the hash of the whole block including transactions Blake2 hasher
+    /// (the transactions are included by the Merkle root within the header)
+    pub fn calculate_hash(&self) -> Hash {
+        self.header.calculate_hash()
+    }
+
+    /// Will calculate the Merkle root over the hashes of all transactions (in order)
+    pub fn calculate_tx_root(&self) -> Hash {
+        merkle_root(&self.get_transaction_hashes())
+    }
+
+    /// Returns the stored Merkle root over all transactions
+    pub fn get_tx_root(&self) -> Hash {
+        self.header.tx_root
+    }
+
+    /// Will create a proof that the transaction at `index` is part of this block
+    /// (check it with `MerkleProof::verify` against the blocks' transaction root)
+    pub fn prove_transaction(&self, index: usize) -> Option<MerkleProof> {
+        merkle_proof(&self.get_transaction_hashes(), index)
+    }
+
+    /// Returns the hashes of all transactions (in order)
+    pub fn get_transaction_hashes(&self) -> Vec<Hash> {
+        self.transactions.iter().map(|transaction| transaction.calculate_hash()).collect()
+    }
+
+    /// Returns the stored hash of the block (None if it was never calculated)
+    pub fn get_hash(&self) -> Option<Hash> {
+        self.hash
+    }
+
+    /// Returns the hash of the block this block points to (None for the genesis block)
+    pub fn get_prev_hash(&self) -> Option<Hash> {
+        self.header.prev_hash
+    }
+
+    /// Will return the producer of the block (the receiver of the coinbase, if there is one)
+    pub fn get_producer(&self) -> Option<&String> {
+        match self.transactions.first().map(|transaction| &transaction.record) {
+            Some(TransactionData::Coinbase { receiver, .. }) => Some(receiver),
+            _ => None,
+        }
+    }
+
+    /// Will return the context the transaction at `position` is executed in,
+    /// given the block is at `height` of a chain using `params`
+    pub fn execution_context(&self, params: &ChainParams, height: usize,
+                             position: usize) -> ExecutionContext {
+        ExecutionContext {
+            is_initial: height == 0,
+            position: Some(position),
+            reward: params.reward_at(height),
+            producer: self.get_producer().cloned(),
+        }
+    }
+
+    /// Will execute all transactions against the world state, given the block is at `height`
+    /// of a chain using `params`. Returns the receipt of every transaction
+    /// Stops at the first transaction which can't be executed (or exceeds the gas limit of
+    /// the block), the changes made until then are not undone
+    /// On chains with Proof of Authority the validator of the block is checked (and credited) first
+    pub fn execute<T: WorldState>(&self, world_state: &mut T, params: &ChainParams,
+                                  height: usize) -> Result<Vec<Receipt>, ChainError> {
+        // Only the validator whose turn it is may produce the block
+        if params.proof_of_authority && height > 0 {
+            authority::accept_validator(self, world_state, height)?;
+        }
+
+        let mut receipts = Vec::new();
+        let mut gas_used: u64 = 0;
+
+        for (i, transaction) in self.transactions.iter().enumerate() {
+            // The whole gas limit of the transaction has to fit into what is left of the block
+            // (except within the genesis block, which may have to set up lots of accounts)
+            if height > 0 && gas_used.saturating_add(transaction.gas_limit) > params.block_gas_limit {
+                return Err(ChainError::BlockGasExceeded { block: height, transaction: i, limit: params.block_gas_limit });
+            }
+
+            let context = self.execution_context(params, height, i);
+            let receipt = transaction.execute(world_state, &context)
+                .map_err(|error| ChainError::TransactionFailed {
+                    block: height,
+                    transaction: i,
+                    account: transaction.from.clone(),
+                    error,
+                })?;
+
+            gas_used = gas_used.saturating_add(receipt.gas_used);
+            receipts.push(receipt);
+        }
+
+        Ok(receipts)
+    }
+
+    /// Appends a transaction to the queue
+    pub fn add_transaction(&mut self, transaction: Transaction) {
+        self.transactions.push(transaction);
+        self.update_hash();
+    }
+
+    /// Will return the amount of transactions
+    pub fn get_transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Will update the hash field by including all transactions currently inside
+    /// the public modifier is only for the demonstration of attacks
+    pub fn update_hash(&mut self) {
+        self.header.tx_root = self.calculate_tx_root();
+        self.hash = Some(self.calculate_hash());
+    }
+
+    /// Checks if the hash is set and matches the blocks interna
+    /// (which includes that the transaction root matches the transactions)
+    pub fn verify_own_hash(&self) -> bool {
+        self.header.tx_root == self.calculate_tx_root() && // Transactions match the header
+            self.hash == Some(self.calculate_hash()) // Hash set and equals calculated hash
+    }
+}
+
+impl AsRef<BlockHeader> for Block {
+    fn as_ref(&self) -> &BlockHeader {
+        &self.header
+    }
+}
+
+impl BlockHeader {
+    /// Will calculate the hash of the block using Blake2 hasher
+    /// (over the canonical encoding of the header, see `encoding`)
+    pub fn calculate_hash(&self) -> Hash {
+        Hash::digest(&encoding::encode_block_header(self))
+    }
+
+    /// Returns the hash of the block this block points to (None for the genesis block)
+    pub fn get_prev_hash(&self) -> Option<Hash> {
+        self.prev_hash
+    }
+
+    /// Returns the Merkle root over all transactions of the block
+    pub fn get_tx_root(&self) -> Hash {
+        self.tx_root
+    }
+
+    /// Returns the nonce that was found while mining
+    pub fn get_nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    /// Returns the creation time (seconds since UNIX epoch)
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the difficulty the block was mined with
+    pub fn get_difficulty(&self) -> u32 {
+        self.difficulty
+    }
+}
+
+impl AsRef<BlockHeader> for BlockHeader {
+    fn as_ref(&self) -> &BlockHeader {
+        self
+    }
+}
+
+impl Transaction {
+    pub fn new(from: String, transaction_data: TransactionData, nonce: u128) -> Self {
+        Transaction {
+            from,
+            nonce,
+            fee: 0,
+            gas_limit: DEFAULT_GAS_LIMIT,
+            gas_price: 0,
+            record: transaction_data,
+            created_at: SystemTime::now(),
+            signature: None,
+        }
+    }
+
+    /// Will create the coinbase paying `amount` tokens to the producer of a block
+    /// (it doesn't use any gas)
+    pub fn coinbase(receiver: String, amount: u128) -> Self {
+        let mut coinbase = Self::new(receiver.clone(), TransactionData::Coinbase { receiver, amount }, 0);
+        coinbase.gas_limit = 0;
+        coinbase
+    }
+
+    /// Will set the fee paid to the producer of the block (has to be done before signing)
+    pub fn set_fee(&mut self, fee: u128) {
+        self.fee = fee;
+    }
+
+    /// Returns the fee paid to the producer of the block
+    pub fn get_fee(&self) -> u128 {
+        self.fee
+    }
+
+    /// Will set the most gas the transaction may use (has to be done before signing)
+    pub fn set_gas_limit(&mut self, gas_limit: u64) {
+        self.gas_limit = gas_limit;
+    }
+
+    /// Returns the most gas the transaction may use
+    pub fn get_gas_limit(&self) -> u64 {
+        self.gas_limit
+    }
+
+    /// Will set the tokens paid for every unit of gas used (has to be done before signing)
+    pub fn set_gas_price(&mut self, gas_price: u128) {
+        self.gas_price = gas_price;
+    }
+
+    /// Returns the tokens paid for every unit of gas used
+    pub fn get_gas_price(&self) -> u128 {
+        self.gas_price
+    }
+
+    /// Will change the world state according to the transactions commands
+    /// A transaction whose contract call fails is still executed: the sender pays the fee
+    /// and the gas used, the failure is part of the receipt (see `gas`)
+    pub fn execute<T: WorldState>(&self, world_state: &mut T,
+                                  context: &ExecutionContext) -> Result<Receipt, TransactionError> {
+        let is_initial = context.is_initial;
+
+        // The coinbase has no sender, it creates the tokens it pays
+        if let TransactionData::Coinbase { receiver, amount } = &self.record {
+            if context.position != Some(0) {
+                return Err(TransactionError::CoinbaseNotFirst);
+            }
+
+            if *amount > context.reward {
+                return Err(TransactionError::CoinbaseTooLarge { amount: *amount, reward: context.reward });
+            }
+
+            let account = match world_state.get_account_by_id_mut(receiver) {
+                Some(account) => account,
+                None => return Err(TransactionError::ReceiverNotFound { account: receiver.clone() }),
+            };
+
+            account.tokens = account.tokens.checked_add(*amount)
+                .ok_or(TransactionError::Overspent { account: receiver.clone(), amount: *amount })?;
+            return Ok(Receipt::default());
+        }
+
+        // The gas limit has to cover the work of the record at least
+        let intrinsic_gas = self.record.intrinsic_gas();
+        if self.gas_limit < intrinsic_gas {
+            return Err(TransactionError::GasLimitTooLow { limit: self.gas_limit, required: intrinsic_gas });
+        }
+
+        // The sender pays for the whole gas limit upfront (the unused gas is refunded)
+        let gas_cost = (self.gas_limit as u128).checked_mul(self.gas_price);
+        let upfront = gas_cost.and_then(|gas_cost| gas_cost.checked_add(self.fee));
+
+        // Check if sending user does exist (no one not on the chain can execute transactions)
+        if let Some(account) = world_state.get_account_by_id_mut(&self.from) {
+            // Every nonce can only be used once and in order (prevents replay attacks)
+            if self.nonce != account.nonce {
+                return Err(TransactionError::NonceMismatch {
+                    account: self.from.clone(),
+                    expected: account.nonce,
+                    actual: self.nonce,
+                });
+            }
+            account.nonce += 1;
+
+            // The fee and the gas are taken first, so the transaction itself can't spend them
+            account.tokens = upfront.and_then(|upfront| account.tokens.checked_sub(upfront))
+                .ok_or(TransactionError::FeeNotPayable { account: self.from.clone(), fee: upfront.unwrap_or(u128::MAX) })?;
+        } else if !is_initial {
+            return Err(TransactionError::AccountNotFound { account: self.from.clone() });
+        } else if upfront != Some(0) {
+            return Err(TransactionError::FeeNotPayable { account: self.from.clone(), fee: upfront.unwrap_or(u128::MAX) });
+        }
+
+        // A failing contract call is part of the block nevertheless (its gas is paid),
+        // every other failure rejects the transaction
+        let mut gas_used = intrinsic_gas;
+        let error = match self.apply_record(world_state, is_initial, &mut gas_used) {
+            Ok(()) => None,
+            Err(error @ TransactionError::ContractFailed { .. }) => Some(error),
+            Err(error) => return Err(error),
+        };
+
+        // The unused gas goes back to the sender, the fee and the used gas to the producer
+        // (both fit, since the whole gas limit could be paid)
+        let refund = (self.gas_limit - gas_used) as u128 * self.gas_price;
+        if refund > 0 {
+            if let Some(sender) = world_state.get_account_by_id_mut(&self.from) {
+                sender.tokens = sender.tokens.saturating_add(refund);
+            }
+        }
+
+        let paid = self.fee + gas_used as u128 * self.gas_price;
+        if paid > 0 {
+            if let Some(producer) = context.producer.as_ref().and_then(|id| world_state.get_account_by_id_mut(id)) {
+                producer.tokens = producer.tokens.saturating_add(paid);
+            }
+        }
+
+        Ok(Receipt { gas_used, error })
+    }
+
+    /// Will change the world state according to the record of the transaction
+    /// (the sender is checked and has paid already). The gas used by contract code is
+    /// added to `gas_used`
+    #[allow(clippy::needless_return)]
+    fn apply_record<T: WorldState>(&self, world_state: &mut T, is_initial: bool,
+                                   gas_used: &mut u64) -> Result<(), TransactionError> {
+        // match is like a switch (pattern matching) in C++ or Java
+        // We will check for the type of transaction here and execute its logic
+        return match &self.record {
+
+            TransactionData::CreateUserAccount(account) => {
+                world_state.create_account(account.into(), AccountType::User)
+            }
+
+            TransactionData::CreateAccount { account, account_type } => {
+                if !is_initial {
+                    return Err(TransactionError::GenesisOnly);
+                }
+                world_state.create_account(account.into(), account_type.clone())
+            }
+
+            TransactionData::ChainInfo { .. } => {
+                if !is_initial {
+                    return Err(TransactionError::GenesisOnly);
+                }
+                Ok(())
+            }
+
+            TransactionData::CreateTokens { receiver, amount } => {
+                if !is_initial {
+                    return Err(TransactionError::TokenCreationNotAllowed);
+                }
+                // Get the receiving user (must exist)
+                return if let Some(account) = world_state.get_account_by_id_mut(receiver) {
+                    account.tokens = account.tokens.checked_add(*amount)
+                        .ok_or(TransactionError::Overspent { account: receiver.clone(), amount: *amount })?;
+                    Ok(())
+                } else {
+                    Err(TransactionError::TokenReceiverNotFound { account: receiver.clone() })
+                };
+            }
+
+            TransactionData::TransferTokens { to, amount } => {
+                let recv_tokens: u128;
+                let sender_tokens: u128;
+
+                if let Some(recv) = world_state.get_account_by_id_mut(to) {
+                    // Be extra careful here, even in the genesis block the sender account has to exist
+                    recv_tokens = recv.tokens;
+                } else {
+                    return Err(TransactionError::ReceiverNotFound { account: to.clone() });
+                }
+
+                if let Some(sender) = world_state.get_account_by_id_mut(&self.from) {
+                    sender_tokens = sender.tokens;
+                } else {
+                    return Err(TransactionError::SenderNotFound { account: self.from.clone() });
+                }
+
+                let balance_recv_new = recv_tokens.checked_add(*amount);
+                let balance_sender_new = sender_tokens.checked_sub(*amount);
+
+                if let (Some(balance_recv_new), Some(balance_sender_new)) = (balance_recv_new, balance_sender_new) {
+                    world_state.get_account_by_id_mut(&self.from).unwrap().tokens = balance_sender_new;
+                    world_state.get_account_by_id_mut(to).unwrap().tokens = balance_recv_new;
+                    return Ok(());
+                } else {
+                    return Err(TransactionError::Overspent { account: self.from.clone(), amount: *amount });
+                }
+            }
+
+            // Handled above already
+            TransactionData::Coinbase { .. } => Ok(()),
+
+            TransactionData::ChangeStoreValue { key, value } => {
+                // Only the own store can be changed (the sender has to exist, even initially)
+                let account = match world_state.get_account_by_id_mut(&self.from) {
+                    Some(account) => account,
+                    None => return Err(TransactionError::AccountNotFound { account: self.from.clone() }),
+                };
+
+                Account::set_store_value(&mut account.store, &self.from, key, value)
+            }
+
+            TransactionData::DeployContract { account, code } => {
+                contract::deploy(world_state, account, code)
+            }
+
+            TransactionData::CallContract { contract, amount, args } => {
+                match world_state.get_account_by_id(contract) {
+                    Some(account) if account.acc_type == AccountType::Contract => {}
+                    _ => return Err(TransactionError::NotAContract { account: contract.clone() }),
+                }
+
+                // The value is sent along with the call
+                match world_state.get_account_by_id(&self.from) {
+                    Some(sender) if sender.tokens >= *amount => {}
+                    Some(_) => return Err(TransactionError::Overspent { account: self.from.clone(), amount: *amount }),
+                    None => return Err(TransactionError::AccountNotFound { account: self.from.clone() }),
+                }
+
+                let call = contract::Call { caller: &self.from, contract, value: *amount, args };
+                let mut gas = self.gas_limit - *gas_used;
+                let result = contract::run(world_state, &call, &mut gas);
+                *gas_used = self.gas_limit - gas;
+                let effects = result
+                    .map_err(|error| TransactionError::ContractFailed { account: contract.clone(), error })?;
+
+                if self.from != *contract {
+                    world_state.get_account_by_id_mut(&self.from).unwrap().tokens -= *amount;
+                }
+                effects.apply(world_state, contract);
+                Ok(())
+            }
+        };
+    }
+
+    /// Will calculate the hash using Blake2 hasher
+    /// (over the canonical encoding of the transaction, see `encoding`)
+    pub fn calculate_hash(&self) -> Hash {
+        Hash::digest(&encoding::encode_transaction(self))
+    }
+
+    /// Will sign the hash of the transaction with the given key
+    /// The key has to belong to the sending account (i.e., its public key is the AccountID)
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), ChainError> {
+        if keypair.account_id() != self.from {
+            return Err(ChainError::ForeignKey { account: self.from.clone() });
+        }
+
+        self.signature = Some(keypair.sign(self.calculate_hash().as_bytes()));
+        Ok(())
+    }
+
+    /// Will hash the transaction and check if the signature is valid
+    /// (i.e., it is created by the owners private key)
+    /// if the message is not signed it will always return false
+    pub fn check_signature(&self) -> bool {
+        if !(self.is_signed()) {
+            return false;
+        }
+
+        verify_signature(&self.from, self.calculate_hash().as_bytes(), self.signature.as_ref().unwrap())
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Will tell if the transaction is a coinbase (see `Transaction::coinbase`)
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.record, TransactionData::Coinbase { .. })
+    }
+}
+
+impl Account {
+    /// Constructor
+    #[allow(clippy::needless_return)]
+    pub fn new(account_type: AccountType) -> Self {
+        return Self {
+            tokens: 0,
+            nonce: 0,
+            acc_type: account_type,
+            store: HashMap::new(),
+            code: Vec::new(),
+        };
+    }
+
+    /// Will return the bytecode of the account (empty unless it is a contract)
+    pub fn get_code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Will change a value within a store (an empty value deletes the key)
+    /// Checks the limits of the store (`MAX_STORE_KEY_LENGTH`, `MAX_STORE_VALUE_LENGTH`
+    /// and `MAX_STORE_ENTRIES`), `account` is the owner of the store
+    fn set_store_value(store: &mut HashMap<String, String>, account: &str,
+                       key: &str, value: &str) -> Result<(), TransactionError> {
+        if key.is_empty() || key.len() > MAX_STORE_KEY_LENGTH {
+            return Err(TransactionError::InvalidStoreKey { key: key.to_string() });
+        }
+
+        if value.len() > MAX_STORE_VALUE_LENGTH {
+            return Err(TransactionError::StoreValueTooLong { key: key.to_string() });
+        }
+
+        // An empty value deletes the key (deleting a missing key is fine)
+        if value.is_empty() {
+            store.remove(key);
+            return Ok(());
+        }
+
+        if !store.contains_key(key) && store.len() >= MAX_STORE_ENTRIES {
+            return Err(TransactionError::StoreFull { account: account.to_string() });
+        }
+
+        store.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Will return the value stored for the given key (if any)
+    pub fn get_store_value(&self, key: &str) -> Option<&String> {
+        self.store.get(key)
+    }
+
+    /// Will return the whole key-value store of the account
+    pub fn get_store(&self) -> &HashMap<String, String> {
+        &self.store
+    }
+
+    /// Will return the amount of tokens the account owns
+    pub fn get_tokens(&self) -> u128 {
+        self.tokens
+    }
+
+    /// Will return the nonce the next transaction of the account has to use
+    pub fn get_nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    /// Will return the type of the account
+    pub fn get_account_type(&self) -> &AccountType {
+        &self.acc_type
+    }
+}
+
+/// Will return the current time as seconds since UNIX epoch
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genesis() -> Block {
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new("alice".into(),
+                                                 TransactionData::CreateUserAccount("alice".into()), 0));
+        genesis.mine(0);
+        genesis
+    }
+
+    fn store_value(bc: &Blockchain, nonce: u128) -> Block {
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(Transaction::new("alice".into(), TransactionData::ChangeStoreValue {
+            key: "k".into(), value: nonce.to_string() }, nonce));
+        block
+    }
+
+    #[test]
+    fn timestamps_may_not_run_far_ahead() {
+        let mut bc = Blockchain::new();
+        bc.set_params(ChainParams { retarget_interval: 2, ..ChainParams::default() });
+        bc.append_block(genesis()).unwrap();
+
+        // A block from the future would make the retarget think the chain is slow
+        let mut warped = store_value(&bc, 0);
+        warped.set_timestamp(u64::MAX / 2);
+        warped.mine(0);
+        assert_eq!(bc.append_block(warped.clone()).err(), Some(ChainError::TimestampTooFarAhead { block: 1 }));
+        let headers = vec![bc.blocks[0].get_header().clone(), warped.get_header().clone()];
+        assert_eq!(bc.params.header_errors(&headers, 1), vec![ChainError::TimestampTooFarAhead { block: 1 }]);
+
+        // A clock being a bit ahead is fine
+        let mut ahead = store_value(&bc, 0);
+        ahead.set_timestamp(unix_timestamp() + MAX_FUTURE_BLOCK_TIME / 2);
+        ahead.mine(0);
+        bc.append_block(ahead).unwrap();
+
+        // Time may not run backwards either
+        let mut before = store_value(&bc, 1);
+        before.set_timestamp(unix_timestamp());
+        before.mine(0);
+        assert_eq!(bc.append_block(before).err(), Some(ChainError::TimestampBeforeParent { block: 2 }));
+    }
+
+    #[test]
+    fn signed_transactions_can_not_be_altered() {
+        let keypair = Keypair::generate();
+        let mut transaction = Transaction::new(keypair.account_id(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 1 }, 0);
+        assert!(!transaction.check_signature());
+        assert_eq!(transaction.sign(&Keypair::generate()),
+                   Err(ChainError::ForeignKey { account: keypair.account_id() }));
+
+        transaction.sign(&keypair).unwrap();
+        assert!(transaction.check_signature());
+
+        let mut tampered = transaction.clone();
+        tampered.nonce = 1;
+        assert!(!tampered.check_signature());
+
+        // Somebody else's signature doesn't make it valid either
+        let other = Keypair::generate();
+        let mut forged = Transaction::new(other.account_id(), transaction.record.clone(), 0);
+        forged.sign(&other).unwrap();
+        tampered.signature = forged.signature;
+        assert!(!tampered.check_signature());
+    }
+
+    #[test]
+    fn unsigned_blocks_are_rejected_when_signatures_are_required() {
+        let keypair = Keypair::generate();
+        let alice = keypair.account_id();
+        let signed = |record, nonce| {
+            let mut transaction = Transaction::new(alice.clone(), record, nonce);
+            transaction.sign(&keypair).unwrap();
+            transaction
+        };
+
+        let mut bc = Blockchain::new();
+        bc.set_require_signatures(true);
+        let mut genesis = Block::new(None);
+        genesis.add_transaction(Transaction::new(alice.clone(), TransactionData::CreateUserAccount(alice.clone()), 0));
+        genesis.mine(0);
+        assert_eq!(bc.append_block(genesis.clone()).map_err(|err| err.code()), Err(4398239048));
+
+        genesis.transactions[0].sign(&keypair).unwrap();
+        genesis.update_hash();
+        genesis.mine(0);
+        bc.append_block(genesis).unwrap();
+
+        let store = TransactionData::ChangeStoreValue { key: "k".into(), value: "v".into() };
+        let mut unsigned = Block::new(bc.get_last_block_hash());
+        unsigned.add_transaction(Transaction::new(alice.clone(), store.clone(), 0));
+        unsigned.mine(0);
+        assert_eq!(bc.append_block(unsigned).err(), Some(ChainError::InvalidSignature {
+            block: 1, transaction: 0, account: alice.clone() }));
+        assert_eq!(bc.submit_transaction(Transaction::new(alice.clone(), store.clone(), 0)).err(),
+                   Some(ChainError::UnsignedTransaction { account: alice.clone() }));
+
+        let mut block = Block::new(bc.get_last_block_hash());
+        block.add_transaction(signed(store, 0));
+        block.mine(0);
+        bc.append_block(block).unwrap();
+        assert!(bc.check_full_validity().is_ok());
+
+        // A signature removed afterwards is found by the validity check
+        bc.blocks[1].transactions[0].signature = None;
+        bc.blocks[1].update_hash();
+        assert_eq!(bc.check_validity().map(|_| ()).map_err(|err| err.code()), Err(4398239048));
+    }
+
+    #[test]
+    fn mined_blocks_meet_their_difficulty() {
+        let mut bc = Blockchain::new();
+        bc.set_difficulty(8);
+        let mut block = genesis();
+        block.mine(8);
+        assert!(block.meets_difficulty(8));
+        assert!(block.get_hash().unwrap().leading_zero_bits() >= 8);
+        assert!(block.verify_own_hash());
+
+        // Less work than required
+        let mut easy = genesis();
+        easy.mine(4);
+        assert_eq!(bc.append_block(easy).err(), Some(ChainError::DifficultyMismatch {
+            block: 0, expected: 8, actual: 4 }));
+
+        // The right difficulty, but the work wasn't done
+        let mut lazy = block.clone();
+        while lazy.header.calculate_hash().leading_zero_bits() >= 8 {
+            lazy.header.nonce += 1;
+        }
+        lazy.update_hash();
+        assert_eq!(bc.append_block(lazy).err(), Some(ChainError::InsufficientWork { block: 0, difficulty: 8 }));
+
+        bc.append_block(block).unwrap();
+    }
+
+    #[test]
+    fn difficulty_follows_the_block_time() {
+        let params = ChainParams {
+            initial_difficulty: 4,
+            retarget_interval: 2,
+            target_block_time: 60,
+            ..ChainParams::default()
+        };
+        let difficulty_after = |timestamps: &[u64]| {
+            let headers: Vec<BlockHeader> = timestamps.iter()
+                .map(|timestamp| BlockHeader { timestamp: *timestamp, difficulty: 4, ..genesis().header })
+                .collect();
+            params.difficulty_at(&headers, headers.len())
+        };
+
+        assert_eq!(params.difficulty_at::<BlockHeader>(&[], 0), 4);
+        assert_eq!(difficulty_after(&[0]), 4);
+        assert_eq!(difficulty_after(&[0, 60]), 4);
+        assert_eq!(difficulty_after(&[0, 30]), 5);
+        assert_eq!(difficulty_after(&[0, 10]), 6);
+        assert_eq!(difficulty_after(&[0, 100]), 3);
+        assert_eq!(difficulty_after(&[0, 200]), 2);
+        // Only every `retarget_interval` blocks
+        assert_eq!(difficulty_after(&[0, 10, 20]), 4);
+        assert_eq!(difficulty_after(&[0, 10, 20, 30]), 6);
+
+        // The chain requires the retargeted difficulty
+        let mut bc = Blockchain::new();
+        bc.set_params(ChainParams { initial_difficulty: 2, ..params });
+        let mut block = genesis();
+        block.set_timestamp(1_000);
+        block.mine(2);
+        bc.append_block(block).unwrap();
+
+        let mut block = store_value(&bc, 0);
+        block.set_timestamp(1_001);
+        block.mine(2);
+        bc.append_block(block).unwrap();
+        assert_eq!(bc.get_difficulty(), 4);
+
+        let mut block = store_value(&bc, 1);
+        block.set_timestamp(1_002);
+        block.mine(2);
+        assert_eq!(bc.append_block(block.clone()).err(), Some(ChainError::DifficultyMismatch {
+            block: 2, expected: 4, actual: 2 }));
+        block.mine(4);
+        bc.append_block(block).unwrap();
+        assert!(bc.check_validity().is_ok());
+    }
+
+    #[test]
+    fn nonces_are_used_once_and_in_order() {
+        let mut bc = Blockchain::new();
+        bc.append_block(genesis()).unwrap();
+        let nonce_error = |block, expected, actual| ChainError::TransactionFailed {
+            block,
+            transaction: 0,
+            account: "alice".into(),
+            error: TransactionError::NonceMismatch { account: "alice".into(), expected, actual },
+        };
+
+        // Nonces may not be skipped
+        let mut skipped = store_value(&bc, 1);
+        skipped.mine(0);
+        assert_eq!(bc.append_block(skipped).err(), Some(nonce_error(1, 0, 1)));
+
+        let mut block = store_value(&bc, 0);
+        block.mine(0);
+        bc.append_block(block.clone()).unwrap();
+        assert_eq!(bc.get_account_by_id("alice").unwrap().get_nonce(), 1);
+        assert_eq!(bc.next_nonce("alice"), Some(1));
+
+        // The same transaction once more (within a new block) is a replay
+        let mut replay = Block::new(bc.get_last_block_hash());
+        replay.add_transaction(block.transactions[0].clone());
+        replay.mine(0);
+        assert_eq!(bc.append_block(replay.clone()).err(), Some(nonce_error(2, 1, 0)));
+        assert_eq!(bc.submit_transaction(block.transactions[0].clone()).err(), Some(ChainError::TransactionRejected {
+            account: "alice".into(),
+            error: TransactionError::NonceMismatch { account: "alice".into(), expected: 1, actual: 0 },
+        }));
+
+        // Sneaked in without `append_block`, the validity check still finds it
+        bc.blocks.push(replay);
+        assert!(bc.blocks[2].verify_own_hash());
+        assert_eq!(bc.check_validity().err(), Some(nonce_error(2, 1, 0)));
+    }
+
+    #[test]
+    fn store_values_go_into_the_senders_store() {
+        let mut state: HashMap<String, Account> = HashMap::new();
+        state.create_account("alice".into(), AccountType::User).unwrap();
+        state.create_account("bob".into(), AccountType::User).unwrap();
+        let change = |state: &mut HashMap<String, Account>, from: &str, key: &str, value: &str| {
+            let nonce = state.get(from).map_or(0, Account::get_nonce);
+            let record = TransactionData::ChangeStoreValue { key: key.into(), value: value.into() };
+            Transaction::new(from.into(), record, nonce)
+                .execute(state, &ExecutionContext::pending(false))
+                .map(|_| ())
+        };
+
+        change(&mut state, "alice", "k", "a").unwrap();
+        change(&mut state, "bob", "k", "b").unwrap();
+        assert_eq!(state.get_store_value("alice", "k"), Some(&"a".to_string()));
+        assert_eq!(state.get_store_value("bob", "k"), Some(&"b".to_string()));
+
+        // Overwriting and deleting only touches the own value
+        change(&mut state, "bob", "k", "c").unwrap();
+        change(&mut state, "alice", "k", "").unwrap();
+        assert_eq!(state.get_store_value("alice", "k"), None);
+        assert_eq!(state.get_store_value("bob", "k"), Some(&"c".to_string()));
+        assert_eq!(state["bob"].get_store().len(), 1);
+
+        assert_eq!(change(&mut state, "carol", "k", "v"),
+                   Err(TransactionError::AccountNotFound { account: "carol".into() }));
+
+        // The limits of a store
+        let long_key = "k".repeat(MAX_STORE_KEY_LENGTH + 1);
+        assert_eq!(change(&mut state, "alice", "", "v"), Err(TransactionError::InvalidStoreKey { key: "".into() }));
+        assert_eq!(change(&mut state, "alice", &long_key, "v"), Err(TransactionError::InvalidStoreKey { key: long_key }));
+        assert_eq!(change(&mut state, "alice", "k", &"v".repeat(MAX_STORE_VALUE_LENGTH + 1)),
+                   Err(TransactionError::StoreValueTooLong { key: "k".into() }));
+
+        for key in 0..MAX_STORE_ENTRIES {
+            change(&mut state, "alice", &key.to_string(), "v").unwrap();
+        }
+        assert_eq!(change(&mut state, "alice", "k", "v"), Err(TransactionError::StoreFull { account: "alice".into() }));
+        change(&mut state, "alice", "0", "w").unwrap();
+        change(&mut state, "bob", "k2", "v").unwrap();
+    }
+
+    #[test]
+    fn created_tokens_can_not_overflow() {
+        let mut bc = Blockchain::new();
+        let mut genesis = genesis();
+        for (nonce, amount) in [u128::MAX, 1].iter().enumerate() {
+            genesis.add_transaction(Transaction::new("alice".into(), TransactionData::CreateTokens {
+                receiver: "alice".into(), amount: *amount }, nonce as u128));
+        }
+        genesis.mine(0);
+
+        assert_eq!(bc.append_block(genesis).err(), Some(ChainError::TransactionFailed {
+            block: 0,
+            transaction: 2,
+            account: "alice".into(),
+            error: TransactionError::Overspent { account: "alice".into(), amount: 1 },
+        }));
+        assert!(bc.is_empty());
+    }
+}
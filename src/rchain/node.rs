@@ -0,0 +1,213 @@
+//! A node keeps a chain within a data directory, so it survives restarts
+//!
+//! The directory contains:
+//!
+//! ```text
+//! spec.toml     the chain spec the chain was initialized with (see `spec`)
+//! blocks.log    every accepted block (see `BlockStore`)
+//! pending.log   the transactions waiting for a block
+//! ```
+
+use super::{Block, Blockchain, ChainError, ChainSpec, Keypair, Transaction};
+use super::storage::{save_transactions, load_transactions};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SPEC_FILE: &str = "spec.toml";
+const BLOCKS_FILE: &str = "blocks.log";
+const PENDING_FILE: &str = "pending.log";
+
+/// A chain persisted to a data directory
+/// Every change made through the node (new blocks and pending transactions) is written
+/// to disk right away
+#[derive(Debug)]
+pub struct Node {
+    dir: PathBuf,
+    chain: Blockchain,
+}
+
+impl Node {
+    /// Will create a new chain from the spec file within the given directory
+    /// (the directory is created if needed, but must not contain a chain yet)
+    pub fn init<P: AsRef<Path>, S: AsRef<Path>>(dir: P, spec_path: S) -> Result<Self, ChainError> {
+        let dir = dir.as_ref().to_path_buf();
+        let spec = ChainSpec::load(&spec_path)?;
+
+        if dir.join(SPEC_FILE).exists() || dir.join(BLOCKS_FILE).exists() {
+            return Err(ChainError::StoreNotEmpty);
+        }
+
+        let setup_error = |err: std::io::Error| ChainError::StoreIo {
+            message: format!("Could not set up `{}`: {}", dir.display(), err),
+        };
+        fs::create_dir_all(&dir).map_err(setup_error)?;
+
+        // The spec is written last, so a failed init leaves no chain behind
+        let mut chain = Blockchain::with_spec(&spec);
+        let stored = chain.open_store(dir.join(BLOCKS_FILE))
+            .and_then(|_| chain.append_block(spec.genesis_block()))
+            .and_then(|_| fs::copy(&spec_path, dir.join(SPEC_FILE)).map_err(setup_error));
+        if let Err(err) = stored {
+            drop(chain);
+            let _ = fs::remove_file(dir.join(BLOCKS_FILE));
+            let _ = fs::remove_file(dir.join(SPEC_FILE));
+            return Err(err);
+        }
+
+        Ok(Node { dir, chain })
+    }
+
+    /// Will load the chain of the given directory (see `init`)
+    /// Pending transactions which are not valid anymore are dropped
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, ChainError> {
+        let dir = dir.as_ref().to_path_buf();
+        let spec = ChainSpec::load(dir.join(SPEC_FILE))?;
+
+        let mut chain = Blockchain::with_spec(&spec);
+        chain.open_store(dir.join(BLOCKS_FILE))?;
+
+        for transaction in load_transactions(dir.join(PENDING_FILE))? {
+            let _ = chain.submit_transaction(transaction);
+        }
+
+        Ok(Node { dir, chain })
+    }
+
+    /// Will return the data directory
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Will return the chain (changes have to be made through the node)
+    pub fn get_chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    /// Will add a transaction to the pending transactions (see `Blockchain::submit_transaction`)
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<(), ChainError> {
+        self.chain.submit_transaction(transaction)?;
+        self.save_pending()
+    }
+
+    /// Will create, mine and append a block containing the pending transactions
+    /// The producer receives the block reward and the fees
+    pub fn produce_block(&mut self, producer: String) -> Result<Block, ChainError> {
+        let mut block = self.chain.build_block_template_for(producer);
+        block.mine(self.chain.get_difficulty());
+
+        self.append_block(block)
+    }
+
+    /// Like `produce_block`, but the block is sealed by a validator
+    /// (needed on chains with Proof of Authority, see `authority`)
+    pub fn produce_sealed_block(&mut self, producer: String, validator: &Keypair) -> Result<Block, ChainError> {
+        let mut block = self.chain.build_block_template_for(producer);
+        block.seal(validator, self.chain.get_difficulty());
+
+        self.append_block(block)
+    }
+
+    fn append_block(&mut self, block: Block) -> Result<Block, ChainError> {
+        self.chain.append_block(block.clone())?;
+        self.save_pending()?;
+        Ok(block)
+    }
+
+    fn save_pending(&self) -> Result<(), ChainError> {
+        save_transactions(self.dir.join(PENDING_FILE), self.chain.get_pending_transactions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{TransactionData, WorldState};
+    use super::*;
+
+    #[test]
+    fn state_survives_restarts() {
+        let dir = std::env::temp_dir().join(format!("rchain-node-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let spec_path = dir.join("genesis.toml");
+        fs::write(&spec_path, "chain_id = \"node-test\"\ninitial_reward = 10\n\
+                               [accounts.alice]\nbalance = 100\n[accounts.bob]\n").unwrap();
+        let data_dir = dir.join("data");
+
+        let mut node = Node::init(&data_dir, &spec_path).unwrap();
+        assert_eq!(Node::init(&data_dir, &spec_path).map_err(|err| err.code()).err(), Some(5820402));
+
+        node.submit_transaction(Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 30 }, 0)).unwrap();
+        node.produce_block("bob".into()).unwrap();
+        node.submit_transaction(Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 5 }, 1)).unwrap();
+        drop(node);
+
+        let mut node = Node::open(&data_dir).unwrap();
+        assert_eq!(node.get_chain().len(), 2);
+        assert_eq!(node.get_chain().get_balance("bob"), Some(40));
+        assert_eq!(node.get_chain().get_pending_transactions().len(), 1);
+        assert_eq!(node.get_chain().next_nonce("alice"), Some(2));
+
+        node.produce_block("alice".into()).unwrap();
+        let node = Node::open(&data_dir).unwrap();
+        assert_eq!(node.get_chain().get_account_by_id("alice").unwrap().get_tokens(), 75);
+        assert!(node.get_chain().get_pending_transactions().is_empty());
+        assert!(node.get_chain().check_full_validity().is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_init_leaves_no_chain_behind() {
+        let dir = std::env::temp_dir().join(format!("rchain-node-failed-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let spec_path = dir.join("genesis.toml");
+        fs::write(&spec_path, "chain_id = \"failed\"\n[accounts.alice]\nbalance = 100\n").unwrap();
+        let data_dir = dir.join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        // The block store can not be created, so the genesis block is never stored
+        std::os::unix::fs::symlink(dir.join("missing").join(BLOCKS_FILE), data_dir.join(BLOCKS_FILE)).unwrap();
+        assert_eq!(Node::init(&data_dir, &spec_path).map_err(|err| err.code()).err(), Some(5820394));
+        assert!(!data_dir.join(SPEC_FILE).exists());
+        assert!(Node::open(&data_dir).is_err());
+
+        // So another attempt starts from scratch
+        let node = Node::init(&data_dir, &spec_path).unwrap();
+        assert_eq!(node.get_chain().get_balance("alice"), Some(100));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn blocks_are_produced_when_signatures_are_required() {
+        let dir = std::env::temp_dir().join(format!("rchain-node-signed-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let keypair = Keypair::generate();
+        let alice = keypair.account_id();
+        let spec_path = dir.join("genesis.toml");
+        fs::write(&spec_path, format!("chain_id = \"signed\"\ninitial_reward = 10\nrequire_signatures = true\n\
+                                       [accounts.{}]\nbalance = 100\n[accounts.bob]\n", alice)).unwrap();
+        let mut node = Node::init(dir.join("data"), &spec_path).unwrap();
+
+        let mut transfer = Transaction::new(alice.clone(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 30 }, 0);
+        transfer.sign(&keypair).unwrap();
+        node.submit_transaction(transfer).unwrap();
+
+        // The coinbase has no sender, so it is accepted without a signature
+        let block = node.produce_block("bob".into()).unwrap();
+        assert!(!block.transactions[0].is_signed());
+        assert_eq!(node.get_chain().get_balance("bob"), Some(40));
+        assert!(node.get_chain().check_full_validity().is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,510 @@
+//! A JSON-RPC 2.0 server over HTTP, so other programs can talk to a running chain
+//!
+//! Every request is a `POST` with a JSON-RPC request (or a batch of them) as body.
+//! The server only listens on localhost and rejects bodies larger than `MAX_BODY` (HTTP 413).
+//! Token amounts and nonces are `u128`, which doesn't fit into a JSON number, so they are passed
+//! as decimal strings.
+//!
+//! | Method              | Params              | Result                                        |
+//! |---------------------|---------------------|-----------------------------------------------|
+//! | `getBlockByHeight`  | `[height]`          | the block (null if there is none)             |
+//! | `getBlockByHash`    | `[hash]`            | the block (null if there is none)             |
+//! | `getAccount`        | `[id]`              | the account (null if there is none)           |
+//! | `getUserIds`        | `[]`                | all account ids (sorted)                      |
+//! | `submitTransaction` | `[transaction]`     | the hash of the pending transaction           |
+//! | `checkValidity`     | `[]`                | `{"valid": bool, "error": error or null}`     |
+//!
+//! Errors of the chain are reported with their code (see `ChainError::code`).
+//! A transaction is passed like it is returned within a block, but only `from`, `nonce` and
+//! `record` are required:
+//!
+//! ```text
+//! {"from": "alice", "nonce": "0", "fee": "1", "gasLimit": 100000, "gasPrice": "1",
+//!  "record": {"type": "TransferTokens", "to": "bob", "amount": "5"},
+//!  "createdAt": {"secs": 1600000000, "nanos": 0}, "signature": null}
+//! ```
+
+use super::{Account, AccountType, Block, Blockchain, ChainError, ChainParams, Hash, Node,
+            Transaction, TransactionData, WorldState};
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Invalid JSON was received
+const PARSE_ERROR: i64 = -32700;
+
+/// The JSON is not a valid request object
+const INVALID_REQUEST: i64 = -32600;
+
+/// The method does not exist
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Invalid method parameters
+const INVALID_PARAMS: i64 = -32602;
+
+/// Largest request body accepted (in bytes)
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// What the RPC server needs from a chain
+pub trait RpcBackend: Send + 'static {
+    fn get_chain(&self) -> &Blockchain;
+
+    fn submit_transaction(&mut self, transaction: Transaction) -> Result<(), ChainError>;
+}
+
+impl RpcBackend for Blockchain {
+    fn get_chain(&self) -> &Blockchain {
+        self
+    }
+
+    fn submit_transaction(&mut self, transaction: Transaction) -> Result<(), ChainError> {
+        Blockchain::submit_transaction(self, transaction)
+    }
+}
+
+/// Submitted transactions are persisted within the data directory
+impl RpcBackend for Node {
+    fn get_chain(&self) -> &Blockchain {
+        Node::get_chain(self)
+    }
+
+    fn submit_transaction(&mut self, transaction: Transaction) -> Result<(), ChainError> {
+        Node::submit_transaction(self, transaction)
+    }
+}
+
+/// A running JSON-RPC server (see the module documentation for the methods)
+/// Requests are handled one after another on a background thread
+pub struct RpcServer {
+    server: Arc<tiny_http::Server>,
+    address: SocketAddr,
+    thread: JoinHandle<()>,
+}
+
+impl RpcServer {
+    /// Will start a server on localhost at the given port (0 picks a free one, see `get_address`)
+    /// The backend is locked while a request is handled
+    pub fn start<B: RpcBackend>(port: u16, backend: Arc<Mutex<B>>) -> Result<Self, ChainError> {
+        let server = tiny_http::Server::http(("127.0.0.1", port))
+            .map_err(|err| ChainError::RpcIo { message: format!("Could not listen on port {}: {}", port, err) })?;
+        let address = server.server_addr().to_ip()
+            .ok_or_else(|| ChainError::RpcIo { message: "Not listening on an IP address".into() })?;
+
+        let server = Arc::new(server);
+        let incoming = Arc::clone(&server);
+        let thread = thread::spawn(move || {
+            for request in incoming.incoming_requests() {
+                handle_http(request, &backend);
+            }
+        });
+
+        Ok(RpcServer { server, address, thread })
+    }
+
+    /// Will return the address the server listens on
+    pub fn get_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Will block until the server is stopped
+    pub fn wait(self) {
+        let _ = self.thread.join();
+    }
+
+    /// Will stop the server (the request being handled is finished first)
+    pub fn stop(self) {
+        self.server.unblock();
+        let _ = self.thread.join();
+    }
+}
+
+fn handle_http<B: RpcBackend>(mut request: tiny_http::Request, backend: &Mutex<B>) {
+    if *request.method() != tiny_http::Method::Post {
+        let _ = request.respond(tiny_http::Response::empty(405));
+        return;
+    }
+
+    // Never read more than the limit (the announced length may be missing or wrong)
+    let too_large = || {
+        let error = error_response(Value::Null, INVALID_REQUEST,
+                                   format!("The request is larger than {} bytes", MAX_BODY));
+        json_response(error.to_string()).with_status_code(413)
+    };
+    if request.body_length().is_some_and(|length| length as u64 > MAX_BODY) {
+        let _ = request.respond(too_large());
+        return;
+    }
+
+    let mut body = Vec::new();
+    if request.as_reader().take(MAX_BODY + 1).read_to_end(&mut body).is_err() {
+        let _ = request.respond(tiny_http::Response::empty(400));
+        return;
+    }
+    if body.len() as u64 > MAX_BODY {
+        let _ = request.respond(too_large());
+        return;
+    }
+
+    let body = match String::from_utf8(body) {
+        Ok(body) => body,
+        Err(_) => {
+            let _ = request.respond(tiny_http::Response::empty(400));
+            return;
+        }
+    };
+
+    // Keep serving even if an earlier request panicked while holding the lock
+    let response = {
+        let mut backend = backend.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        handle_request(&mut *backend, &body)
+    };
+
+    let _ = match response {
+        Some(response) => request.respond(json_response(response)),
+        // Notifications only
+        None => request.respond(tiny_http::Response::empty(204)),
+    };
+}
+
+fn json_response(body: String) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+    tiny_http::Response::from_string(body).with_header(content_type)
+}
+
+/// Will handle the body of a JSON-RPC request (a single request or a batch)
+/// Returns the response body, None if there is nothing to respond (only notifications)
+pub fn handle_request<B: RpcBackend>(backend: &mut B, body: &str) -> Option<String> {
+    let request: Value = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(err) => return Some(error_response(Value::Null, PARSE_ERROR, err.to_string()).to_string()),
+    };
+
+    let response = match request {
+        Value::Array(batch) if batch.is_empty() =>
+            Some(error_response(Value::Null, INVALID_REQUEST, "Empty batch".into())),
+        Value::Array(batch) => {
+            let responses: Vec<Value> = batch.into_iter()
+                .filter_map(|request| handle_single(backend, request))
+                .collect();
+            if responses.is_empty() { None } else { Some(Value::Array(responses)) }
+        }
+        request => handle_single(backend, request),
+    };
+
+    response.map(|response| response.to_string())
+}
+
+fn handle_single<B: RpcBackend>(backend: &mut B, request: Value) -> Option<Value> {
+    let mut request = match request {
+        Value::Object(request) if request.get("jsonrpc") == Some(&json!("2.0")) => request,
+        _ => return Some(error_response(Value::Null, INVALID_REQUEST, "Not a JSON-RPC 2.0 request".into())),
+    };
+
+    // Requests without id are notifications, which are executed but not answered
+    let id = request.remove("id");
+    let method = match request.remove("method") {
+        Some(Value::String(method)) => method,
+        _ => return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST,
+                                        "The method is missing".into())),
+    };
+    let params = Params(request.remove("params").unwrap_or(Value::Null));
+
+    let result = call(backend, &method, &params);
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError { code, message }) => error_response(id, code, message),
+    })
+}
+
+fn call<B: RpcBackend>(backend: &mut B, method: &str, params: &Params) -> Result<Value, RpcError> {
+    let chain = backend.get_chain();
+    match method {
+        "getBlockByHeight" => {
+            let height = params.get(0, "height")?.as_u64()
+                .ok_or_else(|| RpcError::invalid_params("`height` has to be a non-negative integer"))?;
+            Ok(chain.blocks.get(height as usize)
+                .map_or(Value::Null, |block| block_json(block, height as usize)))
+        }
+        "getBlockByHash" => {
+            let hash: Hash = params.get_str(0, "hash")?.parse()?;
+            Ok(chain.get_height(&hash)
+                .map_or(Value::Null, |height| block_json(&chain.blocks[height], height)))
+        }
+        "getAccount" => {
+            let id = params.get_str(0, "id")?;
+            Ok(chain.get_account_by_id(id).map_or(Value::Null, |account| account_json(id, account)))
+        }
+        "getUserIds" => {
+            let mut ids = chain.get_user_ids();
+            ids.sort();
+            Ok(json!(ids))
+        }
+        "submitTransaction" => {
+            let transaction = parse_transaction(params.get(0, "transaction")?)?;
+            let hash = transaction.calculate_hash();
+            backend.submit_transaction(transaction)?;
+            Ok(json!(hash.to_string()))
+        }
+        "checkValidity" => Ok(match chain.check_validity() {
+            Ok(()) => json!({ "valid": true, "error": null }),
+            Err(err) => json!({ "valid": false, "error": { "code": err.code(), "message": err.to_string() } }),
+        }),
+        _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("Unknown method `{}`", method) }),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// An error reported to the client
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: &str) -> Self {
+        RpcError { code: INVALID_PARAMS, message: message.into() }
+    }
+}
+
+/// Errors of the chain keep their code
+impl From<ChainError> for RpcError {
+    fn from(err: ChainError) -> Self {
+        RpcError { code: err.code() as i64, message: err.to_string() }
+    }
+}
+
+/// The params of a request, either by position or by name
+struct Params(Value);
+
+impl Params {
+    fn get(&self, position: usize, name: &str) -> Result<&Value, RpcError> {
+        let value = match &self.0 {
+            Value::Array(params) => params.get(position),
+            Value::Object(params) => params.get(name),
+            _ => None,
+        };
+
+        value.ok_or_else(|| RpcError::invalid_params(&format!("`{}` is missing", name)))
+    }
+
+    fn get_str(&self, position: usize, name: &str) -> Result<&str, RpcError> {
+        self.get(position, name)?.as_str()
+            .ok_or_else(|| RpcError::invalid_params(&format!("`{}` has to be a string", name)))
+    }
+}
+
+fn block_json(block: &Block, height: usize) -> Value {
+    let header = block.get_header();
+    json!({
+        "height": height,
+        "hash": block.calculate_hash().to_string(),
+        "prevHash": header.get_prev_hash().map(|hash| hash.to_string()),
+        "txRoot": header.get_tx_root().to_string(),
+        "nonce": header.get_nonce().to_string(),
+        "timestamp": header.get_timestamp(),
+        "difficulty": header.get_difficulty(),
+        "validator": block.get_validator(),
+        "signature": block.get_signature(),
+        "transactions": block.transactions.iter().map(transaction_json).collect::<Vec<Value>>(),
+    })
+}
+
+fn transaction_json(transaction: &Transaction) -> Value {
+    let created_at = transaction.created_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    json!({
+        "hash": transaction.calculate_hash().to_string(),
+        "from": transaction.from,
+        "nonce": transaction.nonce.to_string(),
+        "fee": transaction.fee.to_string(),
+        "gasLimit": transaction.gas_limit,
+        "gasPrice": transaction.gas_price.to_string(),
+        "createdAt": { "secs": created_at.as_secs(), "nanos": created_at.subsec_nanos() },
+        "record": record_json(&transaction.record),
+        "signature": transaction.signature,
+    })
+}
+
+fn record_json(record: &TransactionData) -> Value {
+    match record {
+        TransactionData::CreateUserAccount(account) =>
+            json!({ "type": "CreateUserAccount", "account": account }),
+        TransactionData::ChangeStoreValue { key, value } =>
+            json!({ "type": "ChangeStoreValue", "key": key, "value": value }),
+        TransactionData::TransferTokens { to, amount } =>
+            json!({ "type": "TransferTokens", "to": to, "amount": amount.to_string() }),
+        TransactionData::CreateTokens { receiver, amount } =>
+            json!({ "type": "CreateTokens", "receiver": receiver, "amount": amount.to_string() }),
+        TransactionData::CreateAccount { account, account_type } =>
+            json!({ "type": "CreateAccount", "account": account, "accountType": account_type_json(account_type) }),
+        TransactionData::ChainInfo { chain_id, params, require_signatures } =>
+            json!({ "type": "ChainInfo", "chainId": chain_id, "params": params_json(params),
+                    "requireSignatures": require_signatures }),
+        TransactionData::Coinbase { receiver, amount } =>
+            json!({ "type": "Coinbase", "receiver": receiver, "amount": amount.to_string() }),
+        TransactionData::DeployContract { account, code } =>
+            json!({ "type": "DeployContract", "account": account, "code": hex::encode(code) }),
+        TransactionData::CallContract { contract, amount, args } =>
+            json!({ "type": "CallContract", "contract": contract, "amount": amount.to_string(), "args": args }),
+    }
+}
+
+fn account_type_json(account_type: &AccountType) -> Value {
+    match account_type {
+        AccountType::User => json!({ "type": "user" }),
+        AccountType::Contract => json!({ "type": "contract" }),
+        AccountType::Validator { correctly_validated_blocks, incorrectly_validated_blocks, you_get_the_idea } =>
+            json!({
+                "type": "validator",
+                "correctlyValidatedBlocks": correctly_validated_blocks.to_string(),
+                "incorrectlyValidatedBlocks": incorrectly_validated_blocks.to_string(),
+                "youGetTheIdea": you_get_the_idea,
+            }),
+    }
+}
+
+fn params_json(params: &ChainParams) -> Value {
+    json!({
+        "initialDifficulty": params.initial_difficulty,
+        "retargetInterval": params.retarget_interval,
+        "targetBlockTime": params.target_block_time,
+        "initialReward": params.initial_reward.to_string(),
+        "halvingInterval": params.halving_interval,
+        "blockGasLimit": params.block_gas_limit,
+        "proofOfAuthority": params.proof_of_authority,
+    })
+}
+
+fn account_json(id: &str, account: &Account) -> Value {
+    let store: Map<String, Value> = account.get_store().iter()
+        .map(|(key, value)| (key.clone(), json!(value)))
+        .collect();
+
+    json!({
+        "id": id,
+        "accountType": account_type_json(account.get_account_type()),
+        "tokens": account.get_tokens().to_string(),
+        "nonce": account.get_nonce().to_string(),
+        "store": store,
+        "code": hex::encode(account.get_code()),
+    })
+}
+
+/// Will build a transaction from its JSON representation (see the module documentation)
+/// Only the records a user may send are accepted
+fn parse_transaction(value: &Value) -> Result<Transaction, RpcError> {
+    let field = |value: &Value, name: &str| -> Result<String, RpcError> {
+        value.get(name).and_then(Value::as_str).map(str::to_string)
+            .ok_or_else(|| RpcError::invalid_params(&format!("`{}` has to be a string", name)))
+    };
+    let number = |value: &Value, name: &str| -> Result<u128, RpcError> {
+        field(value, name)?.parse()
+            .map_err(|_| RpcError::invalid_params(&format!("`{}` has to be a decimal string", name)))
+    };
+
+    let record = value.get("record")
+        .ok_or_else(|| RpcError::invalid_params("`record` is missing"))?;
+    let record = match field(record, "type")?.as_str() {
+        "CreateUserAccount" => TransactionData::CreateUserAccount(field(record, "account")?),
+        "ChangeStoreValue" => TransactionData::ChangeStoreValue {
+            key: field(record, "key")?,
+            value: field(record, "value")?,
+        },
+        "TransferTokens" => TransactionData::TransferTokens {
+            to: field(record, "to")?,
+            amount: number(record, "amount")?,
+        },
+        "DeployContract" => TransactionData::DeployContract {
+            account: field(record, "account")?,
+            code: hex::decode(field(record, "code")?)
+                .map_err(|_| RpcError::invalid_params("`code` has to be hex encoded"))?,
+        },
+        "CallContract" => TransactionData::CallContract {
+            contract: field(record, "contract")?,
+            amount: number(record, "amount")?,
+            args: match record.get("args") {
+                None | Some(Value::Null) => Vec::new(),
+                Some(args) => args.as_array()
+                    .and_then(|args| args.iter().map(|arg| arg.as_str().map(str::to_string)).collect())
+                    .ok_or_else(|| RpcError::invalid_params("`args` has to be a list of strings"))?,
+            },
+        },
+        other => return Err(RpcError::invalid_params(&format!("Records of type `{}` can't be submitted", other))),
+    };
+
+    let mut transaction = Transaction::new(field(value, "from")?, record, number(value, "nonce")?);
+    if value.get("fee").is_some_and(|fee| !fee.is_null()) {
+        transaction.fee = number(value, "fee")?;
+    }
+    if let Some(gas_limit) = value.get("gasLimit").filter(|gas_limit| !gas_limit.is_null()) {
+        transaction.gas_limit = gas_limit.as_u64()
+            .ok_or_else(|| RpcError::invalid_params("`gasLimit` has to be a non-negative integer"))?;
+    }
+    if value.get("gasPrice").is_some_and(|gas_price| !gas_price.is_null()) {
+        transaction.gas_price = number(value, "gasPrice")?;
+    }
+
+    if let Some(created_at) = value.get("createdAt").filter(|created_at| !created_at.is_null()) {
+        let secs = created_at.get("secs").and_then(Value::as_u64);
+        let nanos = created_at.get("nanos").and_then(Value::as_u64)
+            .and_then(|nanos| u32::try_from(nanos).ok())
+            .filter(|nanos| *nanos < 1_000_000_000);
+        transaction.created_at = match (secs, nanos) {
+            (Some(secs), Some(nanos)) => UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+                .ok_or_else(|| RpcError::invalid_params("`createdAt` is too far in the future"))?,
+            _ => return Err(RpcError::invalid_params("`createdAt` needs `secs` and `nanos` (below 1000000000)")),
+        };
+    }
+
+    if let Some(signature) = value.get("signature").filter(|signature| !signature.is_null()) {
+        transaction.signature = Some(signature.as_str()
+            .ok_or_else(|| RpcError::invalid_params("`signature` has to be a string"))?
+            .to_string());
+    }
+
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transaction_json_round_trip() {
+        let mut transaction = Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: u128::MAX }, 3);
+        transaction.set_fee(2);
+        transaction.set_gas_limit(50_000);
+        transaction.set_gas_price(3);
+        transaction.created_at = UNIX_EPOCH + Duration::new(1_600_000_000, 7);
+
+        let parsed = parse_transaction(&transaction_json(&transaction)).ok().unwrap();
+        assert_eq!(parsed.calculate_hash(), transaction.calculate_hash());
+    }
+
+    #[test]
+    fn rejects_what_clients_may_not_send() {
+        let rejected = |transaction: Value| match parse_transaction(&transaction) {
+            Err(RpcError { code, .. }) => code == INVALID_PARAMS,
+            Ok(_) => false,
+        };
+        let transfer = |created_at: Value| json!({ "from": "alice", "nonce": "0", "createdAt": created_at,
+                                                  "record": { "type": "TransferTokens", "to": "bob", "amount": "1" } });
+
+        assert!(!rejected(transfer(json!({ "secs": 1_600_000_000, "nanos": 999_999_999 }))));
+        assert!(rejected(transfer(json!({ "secs": u64::MAX, "nanos": 0 }))));
+        assert!(rejected(transfer(json!({ "secs": 0, "nanos": 1_000_000_000 }))));
+        assert!(rejected(transfer(json!({ "secs": 0, "nanos": u64::from(u32::MAX) + 1 }))));
+
+        // Only the genesis block creates tokens
+        assert!(rejected(json!({ "from": "alice", "nonce": "0",
+                                 "record": { "type": "CreateTokens", "receiver": "alice", "amount": "5" } })));
+    }
+}
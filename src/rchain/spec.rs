@@ -0,0 +1,324 @@
+//! Declarative description of a chain (the chain spec) and the genesis block built from it
+//!
+//! The spec is a small subset of TOML (one `key = value` per line, no arrays or inline tables).
+//! Like in TOML, strings have to be quoted while numbers and booleans must not be.
+//! Top level keys configure the chain, every `[accounts.<id>]` table adds an account to the
+//! genesis block (ids which are no bare TOML keys are quoted, e.g. `[accounts."my id"]`):
+//!
+//! ```text
+//! # Everything after a `#` is ignored
+//! chain_id = "rchain-demo"          # required
+//! timestamp = 1600000000            # creation time of the genesis block (default 0)
+//! initial_difficulty = 12           # see `ChainParams` for these
+//! retarget_interval = 0
+//! target_block_time = 60
+//! initial_reward = 50
+//! halving_interval = 100
+//! block_gas_limit = 10000000
+//! proof_of_authority = false         # validators take turns producing blocks (see `authority`)
+//! require_signatures = false
+//!
+//! [accounts.alice]
+//! type = "user"                     # user (default), contract or validator
+//! balance = 100000000               # default 0
+//! ```
+//!
+//! Building the genesis block only depends on the spec (fixed timestamps, mining starts at
+//! nonce 0), so every node loading the same spec ends up with the same genesis hash.
+
+use super::{AccountType, Block, Blockchain, ChainError, ChainParams, Hash, Transaction, TransactionData};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Everything needed to start a chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainSpec {
+    /// Name of the chain (part of the genesis block, so different chains never share blocks)
+    pub chain_id: String,
+
+    /// Creation time of the genesis block (seconds since UNIX epoch)
+    pub timestamp: u64,
+
+    pub params: ChainParams,
+
+    /// See `Blockchain::set_require_signatures`
+    pub require_signatures: bool,
+
+    /// The accounts created by the genesis block (in order of the file)
+    pub accounts: Vec<GenesisAccount>,
+}
+
+/// An account which exists right from the start
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenesisAccount {
+    pub id: String,
+    pub account_type: AccountType,
+    pub balance: u128,
+}
+
+impl ChainSpec {
+    /// Will read and parse the spec file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ChainError> {
+        let content = fs::read_to_string(path.as_ref()).map_err(|err| ChainError::InvalidSpec {
+            line: None,
+            message: format!("Could not read `{}`: {}", path.as_ref().display(), err),
+        })?;
+
+        Self::parse(&content)
+    }
+
+    /// Will parse the content of a spec file
+    pub fn parse(content: &str) -> Result<Self, ChainError> {
+        let mut chain_id = None;
+        let mut spec = ChainSpec {
+            chain_id: String::new(),
+            timestamp: 0,
+            params: ChainParams::default(),
+            require_signatures: false,
+            accounts: Vec::new(),
+        };
+
+        for (line_num, line) in content.lines().enumerate() {
+            let error = |message: String| ChainError::InvalidSpec { line: Some(line_num), message };
+
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            // A new account table
+            if let Some(header) = line.strip_prefix('[') {
+                let id = header.strip_suffix(']')
+                    .and_then(|header| header.trim().strip_prefix("accounts."))
+                    .map(|id| unquote(id.trim()))
+                    .ok_or_else(|| error(format!("Unknown section `{}`", line)))?;
+
+                if id.is_empty() || spec.accounts.iter().any(|account| account.id == id) {
+                    return Err(error(format!("Account `{}` is empty or defined twice", id)));
+                }
+
+                spec.accounts.push(GenesisAccount {
+                    id: id.to_string(),
+                    account_type: AccountType::User,
+                    balance: 0,
+                });
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(position) => (line[..position].trim(), line[position + 1..].trim()),
+                None => return Err(error(format!("Expected `key = value` but got `{}`", line))),
+            };
+
+            let invalid = |_| error(format!("Invalid value `{}` for `{}`", value, key));
+            let quoted = || string(value)
+                .ok_or_else(|| error(format!("Expected a quoted string for `{}` but got `{}`", key, value)));
+
+            if let Some(account) = spec.accounts.last_mut() {
+                match key {
+                    "type" => account.account_type = match quoted()? {
+                        "user" => AccountType::User,
+                        "contract" => AccountType::Contract,
+                        "validator" => AccountType::Validator {
+                            correctly_validated_blocks: 0,
+                            incorrectly_validated_blocks: 0,
+                            you_get_the_idea: false,
+                        },
+                        other => return Err(error(format!("Unknown account type `{}`", other))),
+                    },
+                    "balance" => account.balance = value.parse().map_err(invalid)?,
+                    _ => return Err(error(format!("Unknown account key `{}`", key))),
+                }
+                continue;
+            }
+
+            match key {
+                "chain_id" => chain_id = Some(quoted()?.to_string()),
+                "timestamp" => spec.timestamp = value.parse().map_err(invalid)?,
+                "initial_difficulty" => spec.params.initial_difficulty = value.parse().map_err(invalid)?,
+                "retarget_interval" => spec.params.retarget_interval = value.parse().map_err(invalid)?,
+                "target_block_time" => spec.params.target_block_time = value.parse().map_err(invalid)?,
+                "initial_reward" => spec.params.initial_reward = value.parse().map_err(invalid)?,
+                "halving_interval" => spec.params.halving_interval = value.parse().map_err(invalid)?,
+                "block_gas_limit" => spec.params.block_gas_limit = value.parse().map_err(invalid)?,
+                "require_signatures" => spec.require_signatures = value.parse()
+                    .map_err(|_| error(format!("Invalid value `{}` for `{}`", value, key)))?,
+                "proof_of_authority" => spec.params.proof_of_authority = value.parse()
+                    .map_err(|_| error(format!("Invalid value `{}` for `{}`", value, key)))?,
+                _ => return Err(error(format!("Unknown key `{}`", key))),
+            }
+        }
+
+        spec.chain_id = chain_id.ok_or(ChainError::InvalidSpec {
+            line: None,
+            message: "`chain_id` is missing".into(),
+        })?;
+
+        Ok(spec)
+    }
+
+    /// Will build (and mine) the genesis block described by the spec
+    /// It first records the chain id and the rules (parameters and whether signatures are
+    /// required), then creates every account and its tokens.
+    /// The transactions have no sender (so no account uses up a nonce) and reserve just
+    /// the gas they need
+    pub fn genesis_block(&self) -> Block {
+        let created_at = UNIX_EPOCH + Duration::from_secs(self.timestamp);
+        let mut transactions = vec![TransactionData::ChainInfo {
+            chain_id: self.chain_id.clone(),
+            params: self.params.clone(),
+            require_signatures: self.require_signatures,
+        }];
+
+        for account in self.accounts.iter() {
+            transactions.push(TransactionData::CreateAccount {
+                account: account.id.clone(),
+                account_type: account.account_type.clone(),
+            });
+
+            if account.balance > 0 {
+                transactions.push(TransactionData::CreateTokens {
+                    receiver: account.id.clone(),
+                    amount: account.balance,
+                });
+            }
+        }
+
+        let mut genesis = Block::new(None);
+        genesis.set_timestamp(self.timestamp);
+        for record in transactions {
+            let mut transaction = Transaction::new(String::new(), record, 0);
+            transaction.set_gas_limit(transaction.record.intrinsic_gas());
+            transaction.created_at = created_at;
+            genesis.add_transaction(transaction);
+        }
+
+        genesis.mine(self.params.initial_difficulty);
+        genesis
+    }
+
+    /// Will return the hash of the genesis block described by the spec
+    pub fn genesis_hash(&self) -> Hash {
+        self.genesis_block().calculate_hash()
+    }
+}
+
+impl Blockchain {
+    /// Will create an empty chain configured by the spec
+    /// Only the genesis block of the spec is accepted as first block
+    /// (useful to load the chain from a store afterwards, see `open_store`)
+    pub fn with_spec(spec: &ChainSpec) -> Self {
+        let mut bc = Blockchain::new();
+        bc.set_params(spec.params.clone());
+        bc.set_require_signatures(spec.require_signatures);
+        bc.genesis_hash = Some(spec.genesis_hash());
+        bc
+    }
+
+    /// Will create a chain configured by the spec, starting with its genesis block
+    pub fn from_spec(spec: &ChainSpec) -> Result<Self, ChainError> {
+        let mut bc = Self::with_spec(spec);
+        bc.append_block(spec.genesis_block())?;
+        Ok(bc)
+    }
+
+    /// Will return the id of the chain (if the genesis block was built from a spec)
+    pub fn get_chain_id(&self) -> Option<&String> {
+        match self.blocks.first()?.transactions.first().map(|transaction| &transaction.record) {
+            Some(TransactionData::ChainInfo { chain_id, .. }) => Some(chain_id),
+            _ => None,
+        }
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    // A `#` inside quotes is part of the value
+    let mut quoted = false;
+    for (position, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..position],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn unquote(value: &str) -> &str {
+    string(value).unwrap_or(value)
+}
+
+/// Will return the content of a quoted string value (None if the value is not quoted)
+fn string(value: &str) -> Option<&str> {
+    value.strip_prefix('"')?.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::WorldState;
+    use super::*;
+
+    const SPEC: &str = r#"
+        # A test chain
+        chain_id = "test # 1"
+        timestamp = 1600000000
+        initial_difficulty = 4
+        initial_reward = 10
+
+        [accounts.alice]
+        balance = 100
+
+        [accounts."validator-1"]
+        type = "validator"
+    "#;
+
+    #[test]
+    fn same_spec_same_genesis() {
+        let spec = ChainSpec::parse(SPEC).unwrap();
+        assert_eq!(spec.chain_id, "test # 1");
+        assert_eq!(spec.params.initial_reward, 10);
+        assert_eq!(spec.accounts.len(), 2);
+        assert_eq!(spec.genesis_hash(), ChainSpec::parse(SPEC).unwrap().genesis_hash());
+
+        let bc = Blockchain::from_spec(&spec).unwrap();
+        assert_eq!(bc.get_chain_id(), Some(&"test # 1".to_string()));
+        assert_eq!(bc.get_balance("alice"), Some(100));
+        assert_eq!(bc.get_account_by_id("alice").unwrap().get_nonce(), 0);
+        assert!(matches!(bc.get_account_by_id("validator-1").unwrap().get_account_type(),
+                         AccountType::Validator { .. }));
+        assert!(bc.check_full_validity().is_ok());
+
+        // Another chain id leads to another genesis block, which is not accepted
+        let other = ChainSpec::parse(&SPEC.replace("test # 1", "test # 2")).unwrap();
+        assert_ne!(other.genesis_hash(), spec.genesis_hash());
+
+        // So do other rules for signatures
+        let signed = ChainSpec::parse(&format!("require_signatures = true\n{}", SPEC)).unwrap();
+        assert_ne!(signed.genesis_hash(), spec.genesis_hash());
+        let mut bc = Blockchain::with_spec(&spec);
+        assert_eq!(bc.append_block(other.genesis_block()).map_err(|err| err.code()), Err(394823100));
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        let line_of = |content: &str| match ChainSpec::parse(content) {
+            Err(ChainError::InvalidSpec { line, .. }) => line,
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(line_of("timestamp = 1"), None);
+        assert_eq!(line_of("chain_id = \"a\"\nfoo = 1"), Some(1));
+        assert_eq!(line_of("chain_id = \"a\"\n[accounts.a]\nbalance = -1"), Some(2));
+        assert_eq!(line_of("chain_id = \"a\"\n[accounts.a]\n[accounts.a]"), Some(2));
+        assert_eq!(line_of("chain_id = \"a\"\n[accounts]"), Some(1));
+        assert_eq!(line_of("chain_id = \"a\"\n[account a]"), Some(1));
+
+        // Strings have to be quoted, numbers and booleans must not be
+        assert_eq!(line_of("chain_id = a"), Some(0));
+        assert_eq!(line_of("chain_id = \"a\"\n[accounts.a]\ntype = user"), Some(2));
+        assert_eq!(line_of("chain_id = \"a\"\ntimestamp = \"1\""), Some(1));
+        assert_eq!(line_of("chain_id = \"a\"\nrequire_signatures = \"true\""), Some(1));
+    }
+}
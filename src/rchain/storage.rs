@@ -0,0 +1,364 @@
+use super::{Block, ChainError, Transaction};
+use super::encoding::{encode_block, decode_block, encode_signed_transaction, decode_signed_transaction};
+use blake2::{Blake2b, Digest};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Amount of bytes of the Blake2 hash which are stored as checksum of a record
+const CHECKSUM_LENGTH: usize = 8;
+
+/// Every record starts with the length of its payload (u32) followed by the checksum
+const RECORD_HEADER_LENGTH: usize = 4 + CHECKSUM_LENGTH;
+
+/// An append only log file which stores every accepted block of a chain
+/// Every block is stored as a single record: `[payload length: u32][checksum][payload]`
+/// where the payload is the canonical encoding of the whole block (see `encoding`)
+/// The file is synced to disk after each record, so an accepted block survives a crash.
+/// If the process dies while writing, the last record will be incomplete. Such a record is
+/// cut off when the file is opened the next time (the block was never accepted anyway)
+///
+/// Cloning the store does not clone the file, both instances will write into the same file
+#[derive(Debug, Clone)]
+pub struct BlockStore {
+    path: PathBuf,
+}
+
+impl BlockStore {
+    /// Will open (or create) the log file and return all blocks stored inside
+    /// A half-written last record is removed from the file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<(Self, Vec<Block>), ChainError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().read(true).write(true).create(true)
+            .truncate(false).open(&path)
+            .map_err(|err| store_io(format!("Could not open `{}`: {}", path.display(), err)))?;
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .map_err(|err| store_io(format!("Could not read: {}", err)))?;
+
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+
+        while offset < content.len() {
+            match read_record(&content[offset..]) {
+                Some((payload, record_length)) => {
+                    let block = decode_block(payload)
+                        .ok_or(ChainError::StoreUndecodable { block: blocks.len() })?;
+
+                    blocks.push(block);
+                    offset += record_length;
+                }
+                None => {
+                    // Only the last record may be broken (crash while writing it)
+                    // Everything else means the file got corrupted
+                    if !is_torn_tail(&content[offset..]) {
+                        return Err(ChainError::StoreCorrupted { block: blocks.len() });
+                    }
+
+                    file.set_len(offset as u64)
+                        .and_then(|_| file.sync_all())
+                        .map_err(|err| store_io(format!("Could not remove incomplete record: {}", err)))?;
+                    break;
+                }
+            }
+        }
+
+        Ok((BlockStore { path }, blocks))
+    }
+
+    /// Will append a block to the log and make sure it is written to disk
+    /// On failure the file is restored to its previous length
+    pub fn append(&self, block: &Block) -> Result<(), ChainError> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)
+            .map_err(|err| store_io(format!("Could not open `{}`: {}", self.path.display(), err)))?;
+
+        let previous_length = file.metadata()
+            .map_err(|err| store_io(format!("Could not read: {}", err)))?
+            .len();
+
+        let payload = encode_block(block);
+        let mut record = Vec::with_capacity(RECORD_HEADER_LENGTH + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum(&payload));
+        record.extend_from_slice(&payload);
+
+        if let Err(err) = file.write_all(&record).and_then(|_| file.sync_all()) {
+            // Best effort, if this fails too, the broken record is cut off on the next start
+            let _ = file.set_len(previous_length);
+            return Err(store_io(format!("Could not write block: {}", err)));
+        }
+
+        Ok(())
+    }
+
+    /// Will cut off all records after the first `block_count` ones
+    /// (used when blocks are removed from the chain again)
+    pub fn truncate(&self, block_count: usize) -> Result<(), ChainError> {
+        let mut file = OpenOptions::new().read(true).write(true).open(&self.path)
+            .map_err(|err| store_io(format!("Could not open `{}`: {}", self.path.display(), err)))?;
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .map_err(|err| store_io(format!("Could not read: {}", err)))?;
+
+        let mut offset = 0;
+        for block_num in 0..block_count {
+            let (_, record_length) = read_record(&content[offset..])
+                .ok_or(ChainError::StoreCorrupted { block: block_num })?;
+            offset += record_length;
+        }
+
+        file.set_len(offset as u64)
+            .and_then(|_| file.sync_all())
+            .map_err(|err| store_io(format!("Could not remove records: {}", err)))
+    }
+
+    /// Path of the underlying log file
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Will replace the content of the file at `path` with the given transactions
+/// (same record format as the block store). The file is written aside and renamed afterwards,
+/// so it either contains the old or the new transactions (even after a crash, both the file
+/// and the rename are synced to disk)
+pub(super) fn save_transactions<P: AsRef<Path>>(path: P, transactions: &[Transaction]) -> Result<(), ChainError> {
+    let path = path.as_ref();
+    let mut content = Vec::new();
+    for transaction in transactions {
+        let payload = encode_signed_transaction(transaction);
+        content.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        content.extend_from_slice(&checksum(&payload));
+        content.extend_from_slice(&payload);
+    }
+
+    let temporary = path.with_extension("tmp");
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::create(&temporary)
+        .and_then(|mut file| file.write_all(&content).and_then(|_| file.sync_all()))
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .and_then(|_| sync_directory(directory))
+        .map_err(|err| store_io(format!("Could not write `{}`: {}", path.display(), err)))
+}
+
+/// Will read the transactions written by `save_transactions` (none if the file doesn't exist)
+pub(super) fn load_transactions<P: AsRef<Path>>(path: P) -> Result<Vec<Transaction>, ChainError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read(path)
+        .map_err(|err| store_io(format!("Could not read `{}`: {}", path.display(), err)))?;
+
+    let mut transactions = Vec::new();
+    let mut offset = 0;
+    while offset < content.len() {
+        let transaction = read_record(&content[offset..])
+            .and_then(|(payload, record_length)| {
+                offset += record_length;
+                decode_signed_transaction(payload)
+            })
+            .ok_or_else(|| store_io(format!("Transaction #{} within `{}` is corrupted",
+                                            transactions.len() + 1, path.display())))?;
+        transactions.push(transaction);
+    }
+
+    Ok(transactions)
+}
+
+/// Makes a rename within the directory durable (directories can't be opened on Windows)
+#[cfg(unix)]
+fn sync_directory(directory: &Path) -> std::io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn store_io(message: String) -> ChainError {
+    ChainError::StoreIo { message }
+}
+
+/// Will read one record from the beginning of `data` and return its payload and
+/// the length of the whole record. Returns None if the record is incomplete or damaged
+fn read_record(data: &[u8]) -> Option<(&[u8], usize)> {
+    if data.len() < RECORD_HEADER_LENGTH {
+        return None;
+    }
+
+    let payload_length = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    let record_length = RECORD_HEADER_LENGTH + payload_length;
+    if data.len() < record_length {
+        return None;
+    }
+
+    let payload = &data[RECORD_HEADER_LENGTH..record_length];
+    if checksum(payload) != data[4..RECORD_HEADER_LENGTH] {
+        return None;
+    }
+
+    Some((payload, record_length))
+}
+
+/// Checks if the (broken) record at the beginning of `data` is a half-written last record:
+/// it reaches until the end of the file and no complete record follows it
+/// (a damaged length field would make any record look like it reaches until the end)
+fn is_torn_tail(data: &[u8]) -> bool {
+    if data.len() < RECORD_HEADER_LENGTH {
+        return true;
+    }
+
+    let payload_length = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    if data.len() > RECORD_HEADER_LENGTH + payload_length {
+        return false;
+    }
+
+    // Every append writes a single record, so a crash leaves nothing complete behind it
+    (1..data.len()).all(|start| read_record(&data[start..]).is_none())
+}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let hash = Blake2b::digest(payload);
+    hash[..CHECKSUM_LENGTH].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Hash, TransactionData};
+    use super::*;
+    use std::fs;
+
+    fn store_with_blocks(name: &str, count: u64) -> (PathBuf, Vec<Block>) {
+        let path = std::env::temp_dir().join(format!("rchain-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let (store, blocks) = BlockStore::open(&path).unwrap();
+        assert!(blocks.is_empty());
+
+        let blocks: Vec<Block> = (0..count).map(|timestamp| {
+            let mut block = Block::new(None);
+            block.set_timestamp(timestamp);
+            store.append(&block).unwrap();
+            block
+        }).collect();
+
+        (path, blocks)
+    }
+
+    fn hashes(blocks: &[Block]) -> Vec<Hash> {
+        blocks.iter().map(|block| block.calculate_hash()).collect()
+    }
+
+    #[test]
+    fn cuts_off_a_torn_last_record() {
+        let (path, blocks) = store_with_blocks("torn", 3);
+        let length = fs::metadata(&path).unwrap().len();
+
+        // The process died while the last record was written
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(length - 5).unwrap();
+        let (_, loaded) = BlockStore::open(&path).unwrap();
+        assert_eq!(hashes(&loaded), hashes(&blocks[..2]));
+
+        // The broken record is gone, so the next block follows right after the second one
+        let (store, _) = BlockStore::open(&path).unwrap();
+        store.append(&blocks[2]).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+        let (_, loaded) = BlockStore::open(&path).unwrap();
+        assert_eq!(hashes(&loaded), hashes(&blocks));
+
+        // Even a cut within the record header
+        file.set_len(3).unwrap();
+        let (_, loaded) = BlockStore::open(&path).unwrap();
+        assert!(loaded.is_empty());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_damaged_records_before_the_last_one() {
+        let (path, blocks) = store_with_blocks("damaged", 3);
+        let content = fs::read(&path).unwrap();
+        let record_length = RECORD_HEADER_LENGTH + u32::from_le_bytes(content[0..4].try_into().unwrap()) as usize;
+        assert_eq!(content.len(), 3 * record_length);
+
+        // A flipped payload byte within the second record
+        let mut damaged = content.clone();
+        damaged[record_length + RECORD_HEADER_LENGTH + 1] ^= 0x01;
+        fs::write(&path, &damaged).unwrap();
+        assert_eq!(BlockStore::open(&path).map_err(|err| err.code()).err(), Some(5820397));
+        assert_eq!(fs::read(&path).unwrap(), damaged);
+        let store = BlockStore { path: path.clone() };
+        assert_eq!(store.truncate(3).map_err(|err| err.code()).err(), Some(5820397));
+
+        // The same within the last record looks like a torn write, so only that block is lost
+        let mut damaged = content.clone();
+        damaged[2 * record_length + RECORD_HEADER_LENGTH + 1] ^= 0x01;
+        fs::write(&path, &damaged).unwrap();
+        let (_, loaded) = BlockStore::open(&path).unwrap();
+        assert_eq!(hashes(&loaded), hashes(&blocks[..2]));
+
+        // A record with a valid checksum but an invalid block
+        let mut content = Vec::new();
+        content.extend_from_slice(&1u32.to_le_bytes());
+        content.extend_from_slice(&checksum(&[0xff]));
+        content.push(0xff);
+        fs::write(&path, &content).unwrap();
+        assert_eq!(BlockStore::open(&path).map_err(|err| err.code()).err(), Some(5820396));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_damaged_length_before_the_last_record() {
+        let (path, _) = store_with_blocks("length", 3);
+        let content = fs::read(&path).unwrap();
+        let record_length = RECORD_HEADER_LENGTH + u32::from_le_bytes(content[0..4].try_into().unwrap()) as usize;
+
+        // The length of the second record now points past the end of the file,
+        // like a torn write would, but the third record is still there
+        let mut damaged = content.clone();
+        damaged[record_length + 2] ^= 0x01;
+        fs::write(&path, &damaged).unwrap();
+        assert_eq!(BlockStore::open(&path).err(), Some(ChainError::StoreCorrupted { block: 1 }));
+        assert_eq!(fs::read(&path).unwrap(), damaged);
+
+        // Shorter than it was, the checksum doesn't match anymore
+        let mut damaged = content;
+        let shorter = (record_length - RECORD_HEADER_LENGTH - 1) as u32;
+        damaged[record_length..record_length + 4].copy_from_slice(&shorter.to_le_bytes());
+        fs::write(&path, &damaged).unwrap();
+        assert_eq!(BlockStore::open(&path).err(), Some(ChainError::StoreCorrupted { block: 1 }));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_saved_transactions() {
+        let path = std::env::temp_dir().join(format!("rchain-pending-{}.log", std::process::id()));
+        let transaction = |nonce| Transaction::new("alice".into(), TransactionData::TransferTokens {
+            to: "bob".into(), amount: 5 }, nonce);
+
+        save_transactions(&path, &[transaction(0), transaction(1)]).unwrap();
+        let latest = transaction(2);
+        save_transactions(&path, std::slice::from_ref(&latest)).unwrap();
+        let loaded = load_transactions(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].calculate_hash(), latest.calculate_hash());
+        assert!(!path.with_extension("tmp").exists());
+
+        fs::remove_file(&path).unwrap();
+        assert!(load_transactions(&path).unwrap().is_empty());
+    }
+}
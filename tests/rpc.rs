@@ -0,0 +1,163 @@
+use rchain_v1::rchain::{Blockchain, ChainSpec, RpcServer};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+
+const SPEC: &str = r#"
+    chain_id = "rpc-test"
+    timestamp = 1600000000
+    initial_reward = 10
+
+    [accounts.alice]
+    balance = 100
+
+    [accounts.bob]
+"#;
+
+/// A minimal HTTP client which posts a body and returns the status and the response body
+fn post(address: SocketAddr, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+                    Content-Length: {}\r\nConnection: close\r\n\r\n{}", address, body.len(), body).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let status = response.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body).to_string();
+    (status, body)
+}
+
+/// Will call a method and return the whole response object
+fn call(address: SocketAddr, method: &str, params: Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let (status, body) = post(address, &request.to_string());
+    assert_eq!(status, 200);
+
+    let response: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["jsonrpc"], "2.0");
+    assert_eq!(response["id"], 1);
+    response
+}
+
+fn start() -> (RpcServer, Arc<Mutex<Blockchain>>) {
+    let spec = ChainSpec::parse(SPEC).unwrap();
+    let chain = Arc::new(Mutex::new(Blockchain::from_spec(&spec).unwrap()));
+    let server = RpcServer::start(0, Arc::clone(&chain)).unwrap();
+    assert!(server.get_address().ip().is_loopback());
+    (server, chain)
+}
+
+#[test]
+fn reads_blocks_and_accounts() {
+    let (server, chain) = start();
+    let address = server.get_address();
+    let genesis_hash = chain.lock().unwrap().get_last_block_hash().unwrap().to_string();
+
+    let block = call(address, "getBlockByHeight", json!([0]))["result"].clone();
+    assert_eq!(block["hash"], genesis_hash);
+    assert_eq!(block["prevHash"], Value::Null);
+    assert_eq!(block["transactions"][0]["record"]["type"], "ChainInfo");
+    assert_eq!(block["transactions"][0]["record"]["chainId"], "rpc-test");
+
+    // Params may also be passed by name
+    let by_hash = call(address, "getBlockByHash", json!({ "hash": genesis_hash }))["result"].clone();
+    assert_eq!(by_hash, block);
+
+    assert_eq!(call(address, "getBlockByHeight", json!([1]))["result"], Value::Null);
+    assert_eq!(call(address, "getBlockByHash", json!(["00"]))["error"]["code"], 7203948);
+
+    let alice = call(address, "getAccount", json!(["alice"]))["result"].clone();
+    assert_eq!(alice["tokens"], "100");
+    assert_eq!(alice["nonce"], "0");
+    assert_eq!(alice["accountType"]["type"], "user");
+    assert_eq!(call(address, "getAccount", json!(["carol"]))["result"], Value::Null);
+
+    assert_eq!(call(address, "getUserIds", json!([]))["result"], json!(["alice", "bob"]));
+    assert_eq!(call(address, "checkValidity", json!([]))["result"], json!({ "valid": true, "error": null }));
+
+    server.stop();
+}
+
+#[test]
+fn submits_transactions_into_the_pending_pool() {
+    let (server, chain) = start();
+    let address = server.get_address();
+
+    let transfer = json!({
+        "from": "alice",
+        "nonce": "0",
+        "fee": "1",
+        "record": { "type": "TransferTokens", "to": "bob", "amount": "30" },
+    });
+    let hash = call(address, "submitTransaction", json!([transfer]))["result"].clone();
+    {
+        let chain = chain.lock().unwrap();
+        assert_eq!(chain.get_pending_transactions().len(), 1);
+        assert_eq!(chain.get_pending_transactions()[0].calculate_hash().to_string(), hash);
+    }
+
+    // Overspending is rejected with the code of the chain error
+    let overspend = json!({
+        "from": "alice",
+        "nonce": "1",
+        "record": { "type": "TransferTokens", "to": "bob", "amount": "1000" },
+    });
+    let error = call(address, "submitTransaction", json!([overspend]))["error"].clone();
+    assert_eq!(error["code"], 2938473);
+
+    // Once mined, the transaction shows up within the block
+    {
+        let mut chain = chain.lock().unwrap();
+        let mut block = chain.build_block_template_for("bob".into());
+        block.mine(chain.get_difficulty());
+        chain.append_block(block).unwrap();
+    }
+    let block = call(address, "getBlockByHeight", json!([1]))["result"].clone();
+    assert_eq!(block["transactions"][1]["hash"], hash);
+    assert_eq!(call(address, "getAccount", json!(["bob"]))["result"]["tokens"], "41");
+
+    server.stop();
+}
+
+#[test]
+fn reports_protocol_errors() {
+    let (server, _) = start();
+    let address = server.get_address();
+
+    let error_code = |body: &str| {
+        let (_, body) = post(address, body);
+        serde_json::from_str::<Value>(&body).unwrap()["error"]["code"].clone()
+    };
+    assert_eq!(error_code("{ not json"), -32700);
+    assert_eq!(error_code(r#"{"id": 1, "method": "getUserIds"}"#), -32600);
+    assert_eq!(error_code(r#"{"jsonrpc": "2.0", "id": 1, "method": "mine"}"#), -32601);
+    assert_eq!(error_code(r#"{"jsonrpc": "2.0", "id": 1, "method": "getAccount", "params": []}"#), -32602);
+    assert_eq!(error_code(r#"{"jsonrpc": "2.0", "id": 1, "method": "submitTransaction",
+                              "params": [{"from": "alice", "nonce": "0", "record": {"type": "Coinbase"}}]}"#), -32602);
+    assert_eq!(error_code(r#"{"jsonrpc": "2.0", "id": 1, "method": "submitTransaction",
+                              "params": [{"from": "alice", "nonce": "0", "createdAt": {"secs": 18446744073709551615, "nanos": 0},
+                                          "record": {"type": "TransferTokens", "to": "bob", "amount": "1"}}]}"#), -32602);
+
+    // A batch gets one response per request, notifications (no id) get none
+    let (status, body) = post(address, r#"[{"jsonrpc": "2.0", "id": 1, "method": "getUserIds"},
+                                            {"jsonrpc": "2.0", "method": "getUserIds"},
+                                            {"jsonrpc": "2.0", "id": 2, "method": "checkValidity"}]"#);
+    assert_eq!(status, 200);
+    let responses: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(responses.as_array().unwrap().len(), 2);
+    assert_eq!(responses[1]["id"], 2);
+
+    let (status, _) = post(address, r#"{"jsonrpc": "2.0", "method": "getUserIds"}"#);
+    assert_eq!(status, 204);
+
+    // The body is limited, even if it's valid otherwise
+    let padded = format!("{}{}", " ".repeat(2 << 20), r#"{"jsonrpc": "2.0", "id": 1, "method": "getUserIds"}"#);
+    let (status, body) = post(address, &padded);
+    assert_eq!(status, 413);
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["error"]["code"], -32600);
+    assert_eq!(call(address, "getUserIds", json!([]))["result"], json!(["alice", "bob"]));
+
+    server.stop();
+}